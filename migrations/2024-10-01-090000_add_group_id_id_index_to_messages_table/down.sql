@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX messages_group_id_id_idx;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+CREATE INDEX messages_group_id_id_idx ON messages (group_id, id);
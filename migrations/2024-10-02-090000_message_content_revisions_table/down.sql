@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE message_content_revisions CASCADE;
+ALTER TABLE messages DROP COLUMN edited_at;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+ALTER TABLE messages ADD COLUMN edited_at TIMESTAMP WITHOUT TIME ZONE;
+
+CREATE TABLE message_content_revisions
+(
+    message_id BIGINT                      NOT NULL REFERENCES messages (id),
+    user_id    BIGINT                      NOT NULL REFERENCES users (id),
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    content    TEXT                        NOT NULL,
+    CONSTRAINT pk_message_content_revisions PRIMARY KEY (message_id, user_id, created_at)
+);
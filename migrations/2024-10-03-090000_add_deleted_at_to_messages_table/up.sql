@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE messages ADD COLUMN deleted_at TIMESTAMP WITHOUT TIME ZONE;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE message_reactions
+(
+    message_id BIGINT                      NOT NULL REFERENCES messages (id),
+    user_id    BIGINT                      NOT NULL REFERENCES users (id),
+    emoji      VARCHAR(32)                 NOT NULL,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    CONSTRAINT pk_message_reactions PRIMARY KEY (message_id, user_id, emoji)
+);
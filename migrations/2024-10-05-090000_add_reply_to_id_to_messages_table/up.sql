@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE messages ADD COLUMN reply_to_id BIGINT REFERENCES messages (id);
+
+CREATE INDEX messages_reply_to_id_id_idx ON messages (reply_to_id, id);
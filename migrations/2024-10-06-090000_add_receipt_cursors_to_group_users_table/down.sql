@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE group_users DROP COLUMN last_read_message_id;
+ALTER TABLE group_users DROP COLUMN last_delivered_message_id;
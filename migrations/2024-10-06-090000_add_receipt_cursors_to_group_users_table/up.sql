@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE group_users ADD COLUMN last_delivered_message_id BIGINT;
+ALTER TABLE group_users ADD COLUMN last_read_message_id BIGINT;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+UPDATE messages SET idempotency_key = NULL
+WHERE id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (PARTITION BY group_id, source_id, idempotency_key ORDER BY id) AS row_number
+        FROM messages
+        WHERE idempotency_key IS NOT NULL
+    ) duplicates
+    WHERE row_number > 1
+);
+
+CREATE UNIQUE INDEX messages_group_id_source_id_idempotency_key_idx ON messages (group_id, source_id, idempotency_key);
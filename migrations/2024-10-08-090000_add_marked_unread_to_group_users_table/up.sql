@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE group_users ADD COLUMN marked_unread BOOLEAN NOT NULL DEFAULT FALSE;
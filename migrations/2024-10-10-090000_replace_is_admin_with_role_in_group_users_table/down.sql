@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE group_users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE group_users SET is_admin = role IN ('owner', 'admin');
+
+ALTER TABLE group_users ALTER COLUMN is_admin DROP DEFAULT;
+
+ALTER TABLE group_users DROP COLUMN role;
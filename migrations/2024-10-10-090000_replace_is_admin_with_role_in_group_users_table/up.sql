@@ -0,0 +1,14 @@
+-- Your SQL goes here
+ALTER TABLE group_users ADD COLUMN role VARCHAR(32) NOT NULL DEFAULT 'member';
+
+UPDATE group_users SET role = 'admin' WHERE is_admin;
+
+UPDATE group_users SET role = 'owner'
+WHERE id IN (
+    SELECT DISTINCT ON (group_id) id FROM group_users WHERE is_admin ORDER BY group_id, created_at, id
+);
+
+UPDATE group_users SET role = 'owner'
+WHERE is_admin AND group_id IN (SELECT id FROM groups WHERE message_request_id IS NOT NULL);
+
+ALTER TABLE group_users DROP COLUMN is_admin;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE group_invites
+(
+    id         BIGINT PRIMARY KEY          NOT NULL,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    group_id   BIGINT                      NOT NULL REFERENCES groups (id),
+    source_id  BIGINT                      NOT NULL REFERENCES users (id),
+    token      VARCHAR(64)                 NOT NULL UNIQUE,
+    expires_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    max_uses   INTEGER,
+    use_count  INTEGER                     NOT NULL DEFAULT 0,
+    revoked_at TIMESTAMP WITHOUT TIME ZONE
+);
+
+CREATE INDEX group_invites_group_id_idx ON group_invites (group_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE group_users DROP COLUMN pinned;
+ALTER TABLE group_users DROP COLUMN archived;
+ALTER TABLE group_users DROP COLUMN muted_until;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE group_users ADD COLUMN muted_until TIMESTAMP;
+ALTER TABLE group_users ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE group_users ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE;
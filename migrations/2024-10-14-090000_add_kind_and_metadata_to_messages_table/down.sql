@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE messages DROP COLUMN metadata;
+ALTER TABLE messages DROP COLUMN kind;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE messages ADD COLUMN kind VARCHAR(32) NOT NULL DEFAULT 'user';
+ALTER TABLE messages ADD COLUMN metadata JSONB;
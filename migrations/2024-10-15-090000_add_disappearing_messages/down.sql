@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX messages_expires_at_idx;
+
+ALTER TABLE messages DROP COLUMN expires_at;
+
+ALTER TABLE groups DROP COLUMN message_ttl_seconds;
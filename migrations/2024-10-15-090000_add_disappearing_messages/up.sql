@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE groups ADD COLUMN message_ttl_seconds INTEGER;
+
+ALTER TABLE messages ADD COLUMN expires_at TIMESTAMP;
+
+CREATE INDEX messages_expires_at_idx ON messages (expires_at) WHERE expires_at IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE message_requests ADD COLUMN status VARCHAR(32) NOT NULL DEFAULT 'pending';
+
+UPDATE message_requests SET status = 'approved' WHERE approved_at IS NOT NULL;
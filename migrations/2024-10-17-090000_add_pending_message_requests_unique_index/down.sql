@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX message_requests_pending_user_ids_idx;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+UPDATE message_requests SET status = 'cancelled', updated_at = NOW()
+WHERE id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (
+            PARTITION BY LEAST(source_id, destination_id), GREATEST(source_id, destination_id) ORDER BY id
+        ) AS row_number
+        FROM message_requests
+        WHERE status = 'pending'
+    ) duplicates
+    WHERE row_number > 1
+);
+
+CREATE UNIQUE INDEX message_requests_pending_user_ids_idx
+ON message_requests (LEAST(source_id, destination_id), GREATEST(source_id, destination_id))
+WHERE status = 'pending';
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE message_requests DROP COLUMN group_id;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+ALTER TABLE message_requests ADD COLUMN group_id BIGINT REFERENCES groups (id);
+
+UPDATE message_requests SET group_id = approved_groups.id
+FROM (
+    SELECT message_request_id, MIN(id) AS id
+    FROM groups
+    WHERE message_request_id IS NOT NULL
+    GROUP BY message_request_id
+) approved_groups
+WHERE approved_groups.message_request_id = message_requests.id;
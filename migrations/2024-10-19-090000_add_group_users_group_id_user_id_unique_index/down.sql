@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX group_users_group_id_user_id_idx;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+DELETE FROM group_users
+WHERE id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (PARTITION BY group_id, user_id ORDER BY id) AS row_number
+        FROM group_users
+    ) duplicates
+    WHERE row_number > 1
+);
+
+CREATE UNIQUE INDEX group_users_group_id_user_id_idx ON group_users (group_id, user_id);
@@ -11,6 +11,7 @@ use serde_json::Value;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info};
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug, Clone)]
 struct JwkKey {
         kid: String,
@@ -1,17 +1,42 @@
+use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use base64::{engine::general_purpose, Engine};
 use chrono::Utc;
+use rand::Rng;
 use rspc::{Error, ErrorCode};
 use web_push::{
         ContentEncoding, SubscriptionInfo, VapidSignatureBuilder, WebPushClient, WebPushMessageBuilder, URL_SAFE_NO_PAD,
 };
 
 use crate::{
-        dtos::{GroupResponseDto, MessageRequestDto, MessageResponseDto, MessageWithGroupResponseDto, UserResponseDto},
-        models::{GroupWithRelationships, MessageWithRelationships},
-        RequestContext,
+        dtos::{
+                receipt_status, ContentValidationErrorDto, GroupInviteRequestDto, GroupInviteResponseDto,
+                GroupMembersRequestDto, GroupRequestDto, GroupResponseDto, GroupSettingsResponseDto,
+                MembershipResponseDto, MessageEditRequestDto, MessageEventDto, MessageExpiredResponseDto,
+                MessagePageResponseDto, MessageRequestDto, MessageResponseDto, MessageWithGroupResponseDto,
+                PageRequestDto, PresignedUploadUrlRequestDto, PresignedUploadUrlResponseDto, ReceiptResponseDto,
+                ThreadResponseDto, TypingResponseDto, UnreadResponseDto,
+        },
+        models::{
+                GroupInvite, GroupPermission, GroupRole, GroupUserSettings, GroupUserWithRelationships,
+                GroupWithRelationships, MessageKind, MessageReaction, MessageReply, MessageWithRelationships,
+                SystemEvent, User,
+        },
+        AppState, RequestContext,
 };
 
+const DEFAULT_PAGE_LIMIT: i32 = 50;
+const MAX_PAGE_LIMIT: i32 = 100;
+const MAX_EMOJI_LENGTH: usize = 32;
+const MAX_NAME_LENGTH: usize = 255;
+const MIN_MESSAGE_TTL_SECONDS: i32 = 30;
+const MAX_MESSAGE_TTL_SECONDS: i32 = 60 * 60 * 24 * 365;
+const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+const REAPER_INTERVAL: Duration = Duration::from_secs(30);
+
 pub async fn get_group(ctx: RequestContext, group_id: String) -> Result<GroupResponseDto, Error> {
         let group_id: i64 = group_id
                 .parse()
@@ -25,16 +50,1414 @@ pub async fn get_group(ctx: RequestContext, group_id: String) -> Result<GroupRes
                 .find_by_id_and_user_id(group_id, auth_user.id)?
                 .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
 
-        let group_response = GroupResponseDto::from(group);
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+fn find_message_page(
+        ctx: &RequestContext,
+        group: &GroupWithRelationships,
+        user_id: i64,
+        reply_to_id: Option<i64>,
+        page_request: PageRequestDto,
+) -> Result<MessagePageResponseDto, Error> {
+        let before: Option<i64> = page_request
+                .before
+                .map(|before| before.parse())
+                .transpose()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid before".into()))?;
+
+        let after: Option<i64> = page_request
+                .after
+                .map(|after| after.parse())
+                .transpose()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid after".into()))?;
+
+        let limit = page_request
+                .limit
+                .unwrap_or(DEFAULT_PAGE_LIMIT)
+                .clamp(1, MAX_PAGE_LIMIT) as i64;
+
+        let mut messages = ctx.app_state.message_repository.find_by_group_id_and_user_id(
+                group.id,
+                user_id,
+                reply_to_id,
+                before,
+                after,
+                limit + 1,
+        )?;
+
+        let has_more = messages.len() as i64 > limit;
+        messages.truncate(limit as usize);
+
+        let next_cursor = if has_more {
+                messages.last().map(|message| message.id.to_string())
+        } else {
+                None
+        };
+
+        let message_page_response = MessagePageResponseDto {
+                messages: messages
+                        .into_iter()
+                        .map(|message| {
+                                let receipt = receipt_status(&group.users, message.id, message.source.id, user_id);
+                                MessageResponseDto {
+                                        receipt,
+                                        ..MessageResponseDto::from((message, user_id))
+                                }
+                        })
+                        .collect(),
+                has_more,
+                next_cursor,
+        };
+
+        Ok(message_page_response)
+}
+
+fn parse_user_ids(user_ids: &[String], excluded_user_ids: &[i64]) -> Result<Vec<i64>, Error> {
+        let mut user_ids = user_ids
+                .iter()
+                .map(|user_id| user_id.parse::<i64>())
+                .collect::<Result<Vec<i64>, _>>()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid userIds".into()))?;
+        user_ids.sort_unstable();
+        user_ids.dedup();
+        user_ids.retain(|user_id| !excluded_user_ids.contains(user_id));
+
+        if user_ids.is_empty() {
+                return Err(Error::new(ErrorCode::BadRequest, "No new members provided".into()));
+        }
+
+        Ok(user_ids)
+}
+
+fn find_approved_users(ctx: &RequestContext, user_id: i64, user_ids: Vec<i64>) -> Result<Vec<User>, Error> {
+        let approved_user_ids = ctx
+                .app_state
+                .message_request_repository
+                .find_approved_user_ids_by_user_id(user_id, user_ids.clone())?;
+        let unapproved_user_ids: Vec<String> = user_ids
+                .iter()
+                .filter(|user_id| !approved_user_ids.contains(user_id))
+                .map(|user_id| user_id.to_string())
+                .collect();
+
+        if !unapproved_user_ids.is_empty() {
+                return Err(Error::new(
+                        ErrorCode::Forbidden,
+                        format!(
+                                "No approved message request with users: {}",
+                                unapproved_user_ids.join(", ")
+                        ),
+                ));
+        }
+
+        ctx.app_state.user_repository.find_by_ids(user_ids)
+}
+
+fn require_permission(
+        group: &GroupWithRelationships,
+        user_id: i64,
+        permission: GroupPermission,
+) -> Result<GroupRole, Error> {
+        let role = group
+                .users
+                .iter()
+                .find(|gu| gu.user.id == user_id)
+                .map(|gu| gu.role)
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        if !role.has_permission(permission) {
+                return Err(Error::new(
+                        ErrorCode::Forbidden,
+                        "Insufficient group permissions".into(),
+                ));
+        }
+
+        Ok(role)
+}
+
+async fn broadcast_membership_change(
+        ctx: &RequestContext,
+        group: &GroupWithRelationships,
+        added_user_ids: Vec<i64>,
+        removed_user_ids: Vec<i64>,
+) {
+        // Removed users are no longer in the group, but their clients still need to drop it
+        let recipient_ids = group
+                .users
+                .iter()
+                .map(|gu| gu.user.id)
+                .chain(removed_user_ids.iter().copied());
+
+        for recipient_id in recipient_ids {
+                let membership_response = MembershipResponseDto {
+                        group: GroupResponseDto::from((group.clone(), recipient_id)),
+                        added_user_ids: added_user_ids.iter().map(|id| id.to_string()).collect(),
+                        removed_user_ids: removed_user_ids.iter().map(|id| id.to_string()).collect(),
+                };
+
+                ctx.app_state
+                        .send_event(recipient_id, MessageEventDto::MembershipChanged(membership_response))
+                        .await;
+        }
+}
+
+async fn broadcast_group_update(ctx: &RequestContext, group: &GroupWithRelationships) {
+        for gu in group.users.iter() {
+                let group_response = GroupResponseDto::from((group.clone(), gu.user.id));
+
+                ctx.app_state
+                        .send_event(gu.user.id, MessageEventDto::GroupUpdated(group_response))
+                        .await;
+        }
+}
+
+fn parse_name(name: Option<String>) -> Result<Option<String>, Error> {
+        let name = name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty());
+
+        if name.as_ref().is_some_and(|name| name.chars().count() > MAX_NAME_LENGTH) {
+                return Err(Error::new(ErrorCode::BadRequest, "Invalid name".into()));
+        }
+
+        Ok(name)
+}
+
+pub async fn create_group(ctx: RequestContext, group_request: GroupRequestDto) -> Result<GroupResponseDto, Error> {
+        let auth_user = ctx.get_auth_user().await?;
+
+        let name = parse_name(Some(group_request.name))?
+                .ok_or(Error::new(ErrorCode::BadRequest, "Invalid name".into()))?;
+
+        let user_ids = parse_user_ids(&group_request.user_ids, &[auth_user.id])?;
+        let users = find_approved_users(&ctx, auth_user.id, user_ids)?;
+
+        let group = {
+                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
+                let group_users = std::iter::once((auth_user.clone(), GroupRole::Owner))
+                        .chain(users.into_iter().map(|user| (user, GroupRole::Member)))
+                        .map(|(user, role)| GroupUserWithRelationships {
+                                id: id_generator.generate(),
+                                created_at: Utc::now().naive_utc(),
+                                updated_at: Utc::now().naive_utc(),
+                                user,
+                                nickname: None,
+                                last_delivered_message_id: None,
+                                last_read_message_id: None,
+                                marked_unread: false,
+                                role,
+                                muted_until: None,
+                                archived: false,
+                                pinned: false,
+                        })
+                        .collect();
+
+                GroupWithRelationships {
+                        id: id_generator.generate(),
+                        created_at: Utc::now().naive_utc(),
+                        updated_at: Utc::now().naive_utc(),
+                        name: Some(name),
+                        message_request_id: None,
+                        archived_at: None,
+                        avatar_url: None,
+                        message_ttl_seconds: None,
+                        users: group_users,
+                }
+        };
+
+        ctx.app_state.group_repository.save(group.clone())?;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn add_group_members(
+        ctx: RequestContext,
+        group_id: String,
+        group_members_request: GroupMembersRequestDto,
+) -> Result<GroupResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::Invite)?;
+
+        let member_ids: Vec<i64> = group.users.iter().map(|gu| gu.user.id).collect();
+        let user_ids = parse_user_ids(&group_members_request.user_ids, &member_ids)?;
+        let users = find_approved_users(&ctx, auth_user.id, user_ids)?;
+        let added_user_ids: Vec<i64> = users.iter().map(|user| user.id).collect();
+
+        let group_users: Vec<GroupUserWithRelationships> = {
+                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
+                users.into_iter()
+                        .map(|user| GroupUserWithRelationships {
+                                id: id_generator.generate(),
+                                created_at: Utc::now().naive_utc(),
+                                updated_at: Utc::now().naive_utc(),
+                                user,
+                                nickname: None,
+                                last_delivered_message_id: None,
+                                last_read_message_id: None,
+                                marked_unread: false,
+                                role: GroupRole::Member,
+                                muted_until: None,
+                                archived: false,
+                                pinned: false,
+                        })
+                        .collect()
+        };
+        ctx.app_state
+                .group_repository
+                .save_group_users(group.id, group_users.clone())?;
+        group.users.extend(group_users);
+
+        broadcast_membership_change(&ctx, &group, added_user_ids.clone(), Vec::new()).await;
+
+        let system_event = SystemEvent::MembersAdded {
+                actor_id: auth_user.id.to_string(),
+                user_ids: added_user_ids.iter().map(|id| id.to_string()).collect(),
+        };
+        create_system_message(&ctx, &group, &auth_user, system_event).await;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn remove_group_member(
+        ctx: RequestContext,
+        group_id: String,
+        user_id: String,
+) -> Result<GroupResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let user_id: i64 = user_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid user_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let role = require_permission(&group, auth_user.id, GroupPermission::Remove)?;
+
+        if user_id == auth_user.id {
+                return Err(Error::new(ErrorCode::BadRequest, "Cannot remove yourself".into()));
+        }
+
+        let member = group
+                .users
+                .iter()
+                .find(|gu| gu.user.id == user_id)
+                .ok_or(Error::new(ErrorCode::NotFound, "Member not found".into()))?;
+
+        if member.role >= role {
+                return Err(Error::new(
+                        ErrorCode::Forbidden,
+                        "Cannot remove a member with an equal or higher role".into(),
+                ));
+        }
+
+        ctx.app_state.group_repository.delete_group_user(group.id, user_id)?;
+        group.users.retain(|gu| gu.user.id != user_id);
+
+        ctx.app_state
+                .typing_expirations
+                .write()
+                .await
+                .remove(&(group.id, user_id));
+
+        broadcast_membership_change(&ctx, &group, Vec::new(), vec![user_id]).await;
+
+        let system_event = SystemEvent::MemberRemoved {
+                actor_id: auth_user.id.to_string(),
+                user_id: user_id.to_string(),
+        };
+        create_system_message(&ctx, &group, &auth_user, system_event).await;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn leave_group(ctx: RequestContext, group_id: String) -> Result<(), Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let promoted_group_user = ctx
+                .app_state
+                .group_repository
+                .delete_group_user_with_handoff(group.id, auth_user.id)?;
+
+        group.users.retain(|gu| gu.user.id != auth_user.id);
+        if let Some(promoted_group_user) = promoted_group_user {
+                for gu in group.users.iter_mut().filter(|gu| gu.id == promoted_group_user.id) {
+                        gu.role = GroupRole::Owner;
+                }
+        }
+
+        ctx.app_state
+                .typing_expirations
+                .write()
+                .await
+                .remove(&(group.id, auth_user.id));
+
+        broadcast_membership_change(&ctx, &group, Vec::new(), vec![auth_user.id]).await;
+
+        if !group.users.is_empty() {
+                let system_event = SystemEvent::MemberLeft {
+                        user_id: auth_user.id.to_string(),
+                };
+                create_system_message(&ctx, &group, &auth_user, system_event).await;
+        }
+
+        Ok(())
+}
+
+pub async fn rename_group(
+        ctx: RequestContext,
+        group_id: String,
+        name: Option<String>,
+) -> Result<GroupResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        if group.users.len() > 2 {
+                require_permission(&group, auth_user.id, GroupPermission::Rename)?;
+        }
+
+        let updated_group = ctx
+                .app_state
+                .group_repository
+                .update_name(group.id, parse_name(name)?)?;
+        group.name = updated_group.name;
+        group.updated_at = updated_group.updated_at;
+
+        broadcast_group_update(&ctx, &group).await;
+
+        let system_event = SystemEvent::GroupRenamed {
+                actor_id: auth_user.id.to_string(),
+                name: group.name.clone(),
+        };
+        create_system_message(&ctx, &group, &auth_user, system_event).await;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn set_member_nickname(
+        ctx: RequestContext,
+        group_id: String,
+        user_id: String,
+        nickname: Option<String>,
+) -> Result<GroupResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let user_id: i64 = user_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid user_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::Post)?;
+
+        let nickname = parse_name(nickname)?;
+
+        let group_user = group
+                .users
+                .iter_mut()
+                .find(|gu| gu.user.id == user_id)
+                .ok_or(Error::new(ErrorCode::NotFound, "Member not found".into()))?;
+
+        let updated_group_user = ctx
+                .app_state
+                .group_repository
+                .update_nickname(group.id, user_id, nickname)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Member not found".into()))?;
+        group_user.nickname = updated_group_user.nickname;
+        group_user.updated_at = updated_group_user.updated_at;
+
+        broadcast_group_update(&ctx, &group).await;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn set_member_role(
+        ctx: RequestContext,
+        group_id: String,
+        user_id: String,
+        role: GroupRole,
+) -> Result<GroupResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let user_id: i64 = user_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid user_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let caller_role = require_permission(&group, auth_user.id, GroupPermission::ManageRoles)?;
+
+        if user_id == auth_user.id {
+                return Err(Error::new(ErrorCode::BadRequest, "Cannot change your own role".into()));
+        }
+
+        let group_user = group
+                .users
+                .iter_mut()
+                .find(|gu| gu.user.id == user_id)
+                .ok_or(Error::new(ErrorCode::NotFound, "Member not found".into()))?;
+
+        // Only owners may grant their own role, and nobody may change the role of an equal or higher member
+        if group_user.role >= caller_role || (role >= caller_role && caller_role != GroupRole::Owner) {
+                return Err(Error::new(
+                        ErrorCode::Forbidden,
+                        "Insufficient group permissions".into(),
+                ));
+        }
+
+        let updated_group_user = ctx
+                .app_state
+                .group_repository
+                .update_role(group.id, user_id, role)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Member not found".into()))?;
+        group_user.role = updated_group_user.role;
+        group_user.updated_at = updated_group_user.updated_at;
+
+        broadcast_group_update(&ctx, &group).await;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn create_group_invite(
+        ctx: RequestContext,
+        group_id: String,
+        group_invite_request: GroupInviteRequestDto,
+) -> Result<GroupInviteResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        if group_invite_request.expires_at <= Utc::now().naive_utc() {
+                return Err(Error::new(ErrorCode::BadRequest, "Invalid expiresAt".into()));
+        }
+
+        if group_invite_request.max_uses.is_some_and(|max_uses| max_uses < 1) {
+                return Err(Error::new(ErrorCode::BadRequest, "Invalid maxUses".into()));
+        }
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::Invite)?;
+
+        let group_invite = {
+                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
+                GroupInvite {
+                        id: id_generator.generate(),
+                        created_at: Utc::now().naive_utc(),
+                        updated_at: Utc::now().naive_utc(),
+                        group_id: group.id,
+                        source_id: auth_user.id,
+                        token: general_purpose::URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>()),
+                        expires_at: group_invite_request.expires_at,
+                        max_uses: group_invite_request.max_uses,
+                        use_count: 0,
+                        revoked_at: None,
+                }
+        };
+        let group_invite = ctx.app_state.group_invite_repository.save(group_invite)?;
+
+        let group_invite_response = GroupInviteResponseDto::from(group_invite);
+
+        Ok(group_invite_response)
+}
+
+pub async fn get_group_invites(ctx: RequestContext, group_id: String) -> Result<Vec<GroupInviteResponseDto>, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::Invite)?;
+
+        let group_invites = ctx
+                .app_state
+                .group_invite_repository
+                .find_active_by_group_id(group.id)?;
+
+        let group_invite_responses = group_invites
+                .into_iter()
+                .map(GroupInviteResponseDto::from)
+                .collect::<Vec<GroupInviteResponseDto>>();
+
+        Ok(group_invite_responses)
+}
+
+pub async fn revoke_group_invite(
+        ctx: RequestContext,
+        group_id: String,
+        group_invite_id: String,
+) -> Result<GroupInviteResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let group_invite_id: i64 = group_invite_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_invite_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::Invite)?;
+
+        let group_invite = ctx
+                .app_state
+                .group_invite_repository
+                .find_by_id_and_group_id(group_invite_id, group.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Invite not found".into()))?;
+
+        let group_invite = match group_invite.revoked_at {
+                Some(_) => group_invite,
+                None => ctx
+                        .app_state
+                        .group_invite_repository
+                        .update_revoked_at(group_invite.id)?,
+        };
+
+        let group_invite_response = GroupInviteResponseDto::from(group_invite);
+
+        Ok(group_invite_response)
+}
+
+pub async fn redeem_group_invite(ctx: RequestContext, token: String) -> Result<GroupResponseDto, Error> {
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group_invite = ctx
+                .app_state
+                .group_invite_repository
+                .find_by_token(&token)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Invite not found".into()))?;
+
+        let now = Utc::now().naive_utc();
+        if group_invite.revoked_at.is_some()
+                || group_invite.expires_at <= now
+                || group_invite
+                        .max_uses
+                        .is_some_and(|max_uses| group_invite.use_count >= max_uses)
+        {
+                return Err(Error::new(ErrorCode::Conflict, "Invite is no longer valid".into()));
+        }
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id(group_invite.group_id)?
+                .filter(|group| group.archived_at.is_none())
+                .ok_or(Error::new(ErrorCode::NotFound, "Invite not found".into()))?;
+
+        if group.users.iter().any(|gu| gu.user.id == auth_user.id) {
+                return Ok(GroupResponseDto::from((group, auth_user.id)));
+        }
+
+        let group_user = {
+                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
+                GroupUserWithRelationships {
+                        id: id_generator.generate(),
+                        created_at: now,
+                        updated_at: now,
+                        user: auth_user.clone(),
+                        nickname: None,
+                        last_delivered_message_id: None,
+                        last_read_message_id: None,
+                        marked_unread: false,
+                        role: GroupRole::Member,
+                        muted_until: None,
+                        archived: false,
+                        pinned: false,
+                }
+        };
+
+        let redeemed = ctx
+                .app_state
+                .group_repository
+                .save_group_user_with_invite(group.id, group_user.clone(), group_invite.id)?
+                .ok_or(Error::new(ErrorCode::Conflict, "Invite is no longer valid".into()))?;
+
+        // A concurrent redeem by the same user already added the membership
+        if !redeemed {
+                let group = ctx
+                        .app_state
+                        .group_repository
+                        .find_by_id_and_user_id(group.id, auth_user.id)?
+                        .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+                return Ok(GroupResponseDto::from((group, auth_user.id)));
+        }
+
+        group.users.push(group_user);
+
+        broadcast_membership_change(&ctx, &group, vec![auth_user.id], Vec::new()).await;
+
+        let system_event = SystemEvent::MemberJoined {
+                user_id: auth_user.id.to_string(),
+        };
+        create_system_message(&ctx, &group, &auth_user, system_event).await;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn create_group_avatar_presigned_upload_url(
+        ctx: RequestContext,
+        group_id: String,
+        presigned_upload_url_request: PresignedUploadUrlRequestDto,
+) -> Result<PresignedUploadUrlResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::UpdateAvatar)?;
+
+        let presigned_url = ctx
+                .app_state
+                .google_cloud_storage_service
+                .get_group_avatar_presigned_upload_url(
+                        format!("g/{}", group.id),
+                        presigned_upload_url_request.content_type,
+                )
+                .await?;
+
+        let presigned_url_response = PresignedUploadUrlResponseDto { url: presigned_url };
+
+        Ok(presigned_url_response)
+}
+
+pub async fn confirm_group_avatar_upload(ctx: RequestContext, group_id: String) -> Result<GroupResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::UpdateAvatar)?;
+
+        let key = format!("g/{}", group.id);
+
+        let generation = ctx
+                .app_state
+                .google_cloud_storage_service
+                .get_group_avatar_generation(key.clone())
+                .await?
+                .ok_or(Error::new(
+                        ErrorCode::BadRequest,
+                        "Group avatar has not been uploaded".into(),
+                ))?;
+
+        // The key never changes, so version the public URL to bust client caches
+        let avatar_url = format!(
+                "{}?v={}",
+                ctx.app_state
+                        .google_cloud_storage_service
+                        .get_group_avatar_public_url(key),
+                generation
+        );
+        let updated_group = ctx.app_state.group_repository.update_avatar_url(group.id, avatar_url)?;
+        group.avatar_url = updated_group.avatar_url;
+        group.updated_at = updated_group.updated_at;
+
+        broadcast_group_update(&ctx, &group).await;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn set_group_message_ttl(
+        ctx: RequestContext,
+        group_id: String,
+        message_ttl_seconds: Option<i32>,
+) -> Result<GroupResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        if message_ttl_seconds.is_some_and(|ttl| !(MIN_MESSAGE_TTL_SECONDS..=MAX_MESSAGE_TTL_SECONDS).contains(&ttl)) {
+                return Err(Error::new(ErrorCode::BadRequest, "Invalid messageTtlSeconds".into()));
+        }
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let mut group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::UpdateMessageTtl)?;
+
+        let updated_group = ctx
+                .app_state
+                .group_repository
+                .update_message_ttl_seconds(group.id, message_ttl_seconds)?;
+        group.message_ttl_seconds = updated_group.message_ttl_seconds;
+        group.updated_at = updated_group.updated_at;
+
+        broadcast_group_update(&ctx, &group).await;
+
+        let system_event = SystemEvent::MessageTtlChanged {
+                actor_id: auth_user.id.to_string(),
+                message_ttl_seconds,
+        };
+        create_system_message(&ctx, &group, &auth_user, system_event).await;
+
+        let group_response = GroupResponseDto::from((group, auth_user.id));
+
+        Ok(group_response)
+}
+
+pub async fn get_group_messages(
+        ctx: RequestContext,
+        group_id: String,
+        page_request: PageRequestDto,
+) -> Result<MessagePageResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let message_page_response = find_message_page(&ctx, &group, auth_user.id, None, page_request)?;
+
+        Ok(message_page_response)
+}
+
+pub async fn get_thread(
+        ctx: RequestContext,
+        group_id: String,
+        message_id: String,
+        page_request: PageRequestDto,
+) -> Result<ThreadResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let message_id: i64 = message_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid message_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let root = ctx
+                .app_state
+                .message_repository
+                .find_by_id_and_group(message_id, group.clone())?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message not found".into()))?;
+
+        let replies = find_message_page(&ctx, &group, auth_user.id, Some(root.id), page_request)?;
+
+        let thread_response = ThreadResponseDto {
+                root: MessageResponseDto::from((root, auth_user.id)),
+                replies,
+        };
+
+        Ok(thread_response)
+}
+
+async fn send_web_push_notifications(
+        ctx: &RequestContext,
+        group: GroupWithRelationships,
+        message: MessageWithRelationships,
+) {
+        for gu in group.users.iter() {
+                if gu.muted_until
+                        .is_some_and(|muted_until| muted_until > Utc::now().naive_utc())
+                {
+                        continue;
+                }
+
+                let event = MessageEventDto::Message(Box::new(MessageWithGroupResponseDto::from((
+                        message.clone(),
+                        gu.user.id,
+                ))));
+
+                let json_event = match serde_json::to_string(&event) {
+                        Ok(json) => json,
+                        Err(e) => {
+                                tracing::error!("Failed to serialize message event: {:?}", e);
+                                continue;
+                        }
+                };
+
+                send_web_push_notification(ctx, gu.user.id, &json_event).await;
+        }
+}
+
+pub(crate) async fn send_web_push_notification(ctx: &RequestContext, user_id: i64, payload: &str) {
+        let push_private_key = env::var("PUSH_PRIVATE_KEY").expect("PUSH_PRIVATE_KEY not set");
+
+        let user_push_subscription = match ctx
+                .app_state
+                .user_push_subscription_repository
+                .find_by_user_id_order_by_created_at_desc(user_id)
+        {
+                Ok(Some(subscription)) => subscription,
+                Ok(None) => return, // No subscription for this user
+                Err(e) => {
+                        tracing::error!("Error fetching user push subscription: {:?}", e);
+                        return;
+                }
+        };
+
+        let subscription_info = SubscriptionInfo::new(
+                user_push_subscription.endpoint,
+                user_push_subscription.p256dh,
+                user_push_subscription.auth,
+        );
+
+        let signature_builder =
+                match VapidSignatureBuilder::from_base64(&push_private_key, URL_SAFE_NO_PAD, &subscription_info) {
+                        Ok(builder) => builder,
+                        Err(e) => {
+                                tracing::error!("Failed to build vapid signature: {:?}", e);
+                                return;
+                        }
+                };
+
+        let signature = match signature_builder.build() {
+                Ok(sig) => sig,
+                Err(e) => {
+                        tracing::error!("Failed to build vapid signature: {:?}", e);
+                        return;
+                }
+        };
+
+        tracing::debug!("web push payload: {:?}", payload);
+
+        let mut web_push_message_build = match WebPushMessageBuilder::new(&subscription_info) {
+                Ok(builder) => builder,
+                Err(e) => {
+                        tracing::error!("Failed to create WebPushMessageBuilder: {:?}", e);
+                        return;
+                }
+        };
+        web_push_message_build.set_payload(ContentEncoding::Aes128Gcm, payload.as_bytes());
+        web_push_message_build.set_vapid_signature(signature);
+
+        let client = match WebPushClient::new() {
+                Ok(client) => client,
+                Err(e) => {
+                        tracing::error!("Failed to create WebPushClient: {:?}", e);
+                        return;
+                }
+        };
+
+        let web_push_message = match web_push_message_build.build() {
+                Ok(message) => message,
+                Err(e) => {
+                        tracing::error!("Failed to build web push message: {:?}", e);
+                        return;
+                }
+        };
+
+        match client.send(web_push_message).await {
+                Ok(_) => tracing::info!("Web push notification sent successfully"),
+                Err(e) => tracing::error!("Failed to send web push message: {:?}", e),
+        }
+}
+
+pub async fn reap_expired_messages(app_state: Arc<AppState>) {
+        let mut interval = tokio::time::interval(REAPER_INTERVAL);
+
+        loop {
+                interval.tick().await;
+
+                let expired_messages = match app_state.message_repository.delete_expired() {
+                        Ok(expired_messages) => expired_messages,
+                        Err(e) => {
+                                tracing::error!("Failed to delete expired messages: {:?}", e);
+                                continue;
+                        }
+                };
+
+                let expired_message_ids_by_group_id = expired_messages.into_iter().fold(
+                        HashMap::<i64, Vec<String>>::new(),
+                        |mut acc, (message_id, group_id)| {
+                                acc.entry(group_id).or_default().push(message_id.to_string());
+                                acc
+                        },
+                );
+
+                for (group_id, message_ids) in expired_message_ids_by_group_id {
+                        let group = match app_state.group_repository.find_by_id(group_id) {
+                                Ok(Some(group)) => group,
+                                Ok(None) => continue,
+                                Err(e) => {
+                                        tracing::error!("Failed to fetch group {}: {:?}", group_id, e);
+                                        continue;
+                                }
+                        };
+
+                        let message_expired_response = MessageExpiredResponseDto {
+                                group_id: group.id.to_string(),
+                                message_ids,
+                        };
+
+                        for gu in group.users.iter() {
+                                app_state
+                                        .send_event(
+                                                gu.user.id,
+                                                MessageEventDto::MessageExpired(message_expired_response.clone()),
+                                        )
+                                        .await;
+                        }
+                }
+        }
+}
+
+pub async fn create_system_message(
+        ctx: &RequestContext,
+        group: &GroupWithRelationships,
+        source: &User,
+        system_event: SystemEvent,
+) {
+        let message = {
+                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
+                ctx.app_state.message_repository.save(MessageWithRelationships {
+                        id: id_generator.generate(),
+                        created_at: Utc::now().naive_utc(),
+                        updated_at: Utc::now().naive_utc(),
+                        group: group.clone(),
+                        source: source.clone(),
+                        content: HashMap::new(),
+                        idempotency_key: None,
+                        edited_at: None,
+                        deleted_at: None,
+                        reactions: Vec::new(),
+                        reply_to: None,
+                        kind: MessageKind::System,
+                        system_event: Some(system_event),
+                        expires_at: None,
+                })
+        };
+
+        match message {
+                Ok(message) => broadcast_message(ctx, &message).await,
+                Err(e) => tracing::error!("Failed to create system message: {:?}", e),
+        }
+}
+
+async fn broadcast_message(ctx: &RequestContext, message: &MessageWithRelationships) {
+        for gu in message.group.users.iter() {
+                tracing::debug!("Sending message to user {}", gu.user.id);
+                let message_response = MessageWithGroupResponseDto::from((message.clone(), gu.user.id));
+                let delivered = ctx
+                        .app_state
+                        .send_event(gu.user.id, MessageEventDto::Message(Box::new(message_response)))
+                        .await;
+
+                if !delivered || gu.user.id == message.source.id || message.deleted_at.is_some() {
+                        continue;
+                }
+
+                if gu.last_delivered_message_id.is_some_and(|id| id >= message.id) {
+                        continue;
+                }
+
+                match ctx.app_state.group_repository.update_last_delivered_message_id(
+                        message.group.id,
+                        gu.user.id,
+                        message.id,
+                ) {
+                        Ok(group_user) => {
+                                ctx.app_state
+                                        .send_event(
+                                                message.source.id,
+                                                MessageEventDto::Receipt(ReceiptResponseDto::from(group_user)),
+                                        )
+                                        .await;
+                        }
+                        Err(e) => {
+                                tracing::error!("Failed to record message delivery: {:?}", e);
+                        }
+                }
+        }
+}
+
+fn parse_content(content: HashMap<String, String>, recipient_ids: &[i64]) -> Result<HashMap<i64, String>, Error> {
+        let mut parsed_content = HashMap::new();
+        let mut malformed_user_ids = Vec::new();
+        for (user_id, ciphertext) in content {
+                match user_id.parse::<i64>() {
+                        Ok(parsed_user_id) => {
+                                parsed_content.insert(parsed_user_id, ciphertext);
+                        }
+                        Err(_) => malformed_user_ids.push(user_id),
+                }
+        }
+
+        let missing_user_ids: Vec<String> = recipient_ids
+                .iter()
+                .filter(|id| !parsed_content.contains_key(id))
+                .map(|id| id.to_string())
+                .collect();
+
+        let unexpected_user_ids: Vec<String> = parsed_content
+                .keys()
+                .filter(|id| !recipient_ids.contains(id))
+                .map(|id| id.to_string())
+                .collect();
+
+        if missing_user_ids.is_empty() && unexpected_user_ids.is_empty() && malformed_user_ids.is_empty() {
+                return Ok(parsed_content);
+        }
+
+        let validation_error = ContentValidationErrorDto {
+                missing_user_ids,
+                unexpected_user_ids,
+                malformed_user_ids,
+        };
+
+        Err(Error::new(
+                ErrorCode::BadRequest,
+                serde_json::to_string(&validation_error).unwrap_or_else(|_| "Invalid content".into()),
+        ))
+}
+
+fn find_replayed_message(
+        ctx: &RequestContext,
+        group: &GroupWithRelationships,
+        user_id: i64,
+        message_request: &MessageRequestDto,
+) -> Result<Option<MessageWithRelationships>, Error> {
+        match message_request.idempotency_key.as_ref() {
+                Some(idempotency_key) => ctx
+                        .app_state
+                        .message_repository
+                        .find_by_group_and_source_id_and_idempotency_key(group.clone(), user_id, idempotency_key),
+                None => Ok(None),
+        }
+}
+
+pub async fn create_group_message(
+        ctx: RequestContext,
+        group_id: String,
+        message_request: MessageRequestDto,
+) -> Result<MessageResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::Post)?;
+
+        if let Some(message) = find_replayed_message(&ctx, &group, auth_user.id, &message_request)? {
+                return Ok(MessageResponseDto::from((message, auth_user.id)));
+        }
+
+        let reply_to = match message_request.reply_to_id.as_ref() {
+                Some(reply_to_id) => {
+                        let reply_to_id: i64 = reply_to_id
+                                .parse()
+                                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid replyToId".into()))?;
+
+                        let reply_to = ctx
+                                .app_state
+                                .message_repository
+                                .find_by_id_and_group(reply_to_id, group.clone())?
+                                .ok_or(Error::new(ErrorCode::BadRequest, "Reply to message not found".into()))?;
+
+                        Some(MessageReply::from(reply_to))
+                }
+                None => None,
+        };
+
+        let recipient_ids: Vec<i64> = group.users.iter().map(|gu| gu.user.id).collect();
+        let content = parse_content(message_request.content.clone(), &recipient_ids)?;
+
+        let saved_message = {
+                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
+                ctx.app_state.message_repository.save(MessageWithRelationships {
+                        id: id_generator.generate(),
+                        created_at: Utc::now().naive_utc(),
+                        updated_at: Utc::now().naive_utc(),
+                        group: group.clone(),
+                        source: auth_user.clone(),
+                        content,
+                        idempotency_key: message_request.idempotency_key.clone(),
+                        edited_at: None,
+                        deleted_at: None,
+                        reactions: Vec::new(),
+                        reply_to,
+                        kind: MessageKind::User,
+                        system_event: None,
+                        expires_at: group
+                                .message_ttl_seconds
+                                .map(|ttl| Utc::now().naive_utc() + chrono::Duration::seconds(ttl.into())),
+                })
+        };
+
+        // A concurrent retry may have won the race on the idempotency key index
+        let message = match saved_message {
+                Ok(message) => message,
+                Err(error) => {
+                        return match find_replayed_message(&ctx, &group, auth_user.id, &message_request)? {
+                                Some(message) => Ok(MessageResponseDto::from((message, auth_user.id))),
+                                None => Err(error),
+                        };
+                }
+        };
+
+        broadcast_message(&ctx, &message).await;
+
+        let message_for_notification = message.clone();
+
+        send_web_push_notifications(&ctx, group, message_for_notification).await;
+
+        let message_response = MessageResponseDto::from((message, auth_user.id));
+
+        Ok(message_response)
+}
+
+pub async fn edit_group_message(
+        ctx: RequestContext,
+        group_id: String,
+        message_id: String,
+        message_edit_request: MessageEditRequestDto,
+) -> Result<MessageResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let message_id: i64 = message_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid message_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        require_permission(&group, auth_user.id, GroupPermission::Post)?;
+
+        let mut message = ctx
+                .app_state
+                .message_repository
+                .find_by_id_and_group(message_id, group)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message not found".into()))?;
+
+        if message.kind == MessageKind::System {
+                return Err(Error::new(
+                        ErrorCode::Forbidden,
+                        "System messages cannot be edited".into(),
+                ));
+        }
+
+        if message.source.id != auth_user.id {
+                return Err(Error::new(
+                        ErrorCode::Forbidden,
+                        "Only the sender can edit a message".into(),
+                ));
+        }
+
+        if message.deleted_at.is_some() {
+                return Err(Error::new(ErrorCode::Conflict, "Message has been deleted".into()));
+        }
+
+        let recipient_ids: Vec<i64> = message.content.keys().copied().collect();
+        let content = parse_content(message_edit_request.content, &recipient_ids)?;
+
+        message.updated_at = Utc::now().naive_utc();
+        message.edited_at = Some(message.updated_at);
+        message.content = content;
+        let message = ctx.app_state.message_repository.save_revision(message)?;
+
+        broadcast_message(&ctx, &message).await;
+
+        let message_response = MessageResponseDto::from((message, auth_user.id));
+
+        Ok(message_response)
+}
+
+pub async fn delete_group_message(
+        ctx: RequestContext,
+        group_id: String,
+        message_id: String,
+) -> Result<MessageResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let message_id: i64 = message_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid message_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let mut message = ctx
+                .app_state
+                .message_repository
+                .find_by_id_and_group(message_id, group.clone())?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message not found".into()))?;
+
+        if message.kind == MessageKind::System {
+                return Err(Error::new(
+                        ErrorCode::Forbidden,
+                        "System messages cannot be deleted".into(),
+                ));
+        }
 
-        Ok(group_response)
+        if message.source.id != auth_user.id {
+                require_permission(&group, auth_user.id, GroupPermission::DeleteOthersMessages)?;
+        }
+
+        if message.deleted_at.is_none() {
+                message.updated_at = Utc::now().naive_utc();
+                message.deleted_at = Some(message.updated_at);
+                message = ctx.app_state.message_repository.save_tombstone(message)?;
+
+                broadcast_message(&ctx, &message).await;
+        }
+
+        let message_response = MessageResponseDto::from((message, auth_user.id));
+
+        Ok(message_response)
 }
 
-pub async fn get_group_messages(ctx: RequestContext, group_id: String) -> Result<Vec<MessageResponseDto>, Error> {
+pub async fn add_group_message_reaction(
+        ctx: RequestContext,
+        group_id: String,
+        message_id: String,
+        emoji: String,
+) -> Result<MessageResponseDto, Error> {
         let group_id: i64 = group_id
                 .parse()
                 .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
 
+        let message_id: i64 = message_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid message_id".into()))?;
+
+        if emoji.is_empty() || emoji.chars().count() > MAX_EMOJI_LENGTH {
+                return Err(Error::new(ErrorCode::BadRequest, "Invalid emoji".into()));
+        }
+
         let auth_user = ctx.get_auth_user().await?;
 
         let group = ctx
@@ -43,132 +1466,201 @@ pub async fn get_group_messages(ctx: RequestContext, group_id: String) -> Result
                 .find_by_id_and_user_id(group_id, auth_user.id)?
                 .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
 
-        let messages = ctx.app_state.message_repository.find_by_group_id(group.id)?;
+        require_permission(&group, auth_user.id, GroupPermission::Post)?;
 
-        let message_responses = messages
-                .into_iter()
-                .map(|message| MessageResponseDto {
-                        id: message.id.to_string(),
-                        created_at: message.created_at,
-                        updated_at: message.updated_at,
-                        source: UserResponseDto::from(message.source),
-                        content: message
-                                .content
-                                .get(&auth_user.id)
-                                .expect("Got message not for auth user")
-                                .clone(),
-                        idempotency_key: message.idempotency_key,
-                })
-                .collect::<Vec<MessageResponseDto>>();
+        let message = ctx
+                .app_state
+                .message_repository
+                .find_by_id_and_group(message_id, group.clone())?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message not found".into()))?;
+
+        if message.deleted_at.is_some() {
+                return Err(Error::new(ErrorCode::Conflict, "Message has been deleted".into()));
+        }
+
+        ctx.app_state.message_reaction_repository.save(MessageReaction {
+                message_id: message.id,
+                user_id: auth_user.id,
+                emoji,
+                created_at: Utc::now().naive_utc(),
+        })?;
+
+        let message = ctx
+                .app_state
+                .message_repository
+                .find_by_id_and_group(message.id, group)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message not found".into()))?;
+
+        broadcast_message(&ctx, &message).await;
 
-        Ok(message_responses)
+        let message_response = MessageResponseDto::from((message, auth_user.id));
+
+        Ok(message_response)
 }
 
-async fn send_web_push_notifications(
-        ctx: &RequestContext,
-        group: GroupWithRelationships,
-        message: MessageWithRelationships,
-) {
-        let push_private_key = env::var("PUSH_PRIVATE_KEY").expect("PUSH_PRIVATE_KEY not set");
+pub async fn remove_group_message_reaction(
+        ctx: RequestContext,
+        group_id: String,
+        message_id: String,
+        emoji: String,
+) -> Result<MessageResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
 
-        for gu in group.users.iter() {
-                let user_push_subscription = match ctx
-                        .app_state
-                        .user_push_subscription_repository
-                        .find_by_user_id_order_by_created_at_desc(gu.user.id)
-                {
-                        Ok(Some(subscription)) => subscription,
-                        Ok(None) => continue, // No subscription for this user
-                        Err(e) => {
-                                tracing::error!("Error fetching user push subscription: {:?}", e);
-                                continue;
-                        }
-                };
+        let message_id: i64 = message_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid message_id".into()))?;
 
-                let subscription_info = SubscriptionInfo::new(
-                        user_push_subscription.endpoint,
-                        user_push_subscription.p256dh,
-                        user_push_subscription.auth,
-                );
+        let auth_user = ctx.get_auth_user().await?;
 
-                let signature_builder = match VapidSignatureBuilder::from_base64(
-                        &push_private_key,
-                        URL_SAFE_NO_PAD,
-                        &subscription_info,
-                ) {
-                        Ok(builder) => builder,
-                        Err(e) => {
-                                tracing::error!("Failed to build vapid signature: {:?}", e);
-                                continue;
-                        }
-                };
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
 
-                let signature = match signature_builder.build() {
-                        Ok(sig) => sig,
-                        Err(e) => {
-                                tracing::error!("Failed to build vapid signature: {:?}", e);
-                                continue;
-                        }
-                };
+        let message = ctx
+                .app_state
+                .message_repository
+                .find_by_id_and_group(message_id, group.clone())?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message not found".into()))?;
 
-                let message_response = MessageWithGroupResponseDto {
-                        id: message.id.to_string(),
-                        created_at: message.created_at,
-                        updated_at: message.updated_at,
-                        group: GroupResponseDto::from(message.group.clone()),
-                        source: UserResponseDto::from(message.source.clone()),
-                        content: message.content.get(&gu.user.id).unwrap().clone(),
-                        idempotency_key: message.idempotency_key.clone(),
-                };
+        ctx.app_state
+                .message_reaction_repository
+                .delete_by_message_id_and_user_id_and_emoji(message.id, auth_user.id, emoji)?;
 
-                let json_message_response = match serde_json::to_string(&message_response) {
-                        Ok(json) => json,
-                        Err(e) => {
-                                tracing::error!("Failed to serialize message response: {:?}", e);
-                                continue;
-                        }
-                };
+        let message = ctx
+                .app_state
+                .message_repository
+                .find_by_id_and_group(message.id, group)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message not found".into()))?;
 
-                tracing::debug!("json_message_response: {:?}", json_message_response);
+        broadcast_message(&ctx, &message).await;
 
-                let mut web_push_message_build = match WebPushMessageBuilder::new(&subscription_info) {
-                        Ok(builder) => builder,
-                        Err(e) => {
-                                tracing::error!("Failed to create WebPushMessageBuilder: {:?}", e);
-                                continue;
-                        }
-                };
-                web_push_message_build.set_payload(ContentEncoding::Aes128Gcm, json_message_response.as_bytes());
-                web_push_message_build.set_vapid_signature(signature);
+        let message_response = MessageResponseDto::from((message, auth_user.id));
 
-                let client = match WebPushClient::new() {
-                        Ok(client) => client,
-                        Err(e) => {
-                                tracing::error!("Failed to create WebPushClient: {:?}", e);
-                                continue;
-                        }
-                };
+        Ok(message_response)
+}
 
-                let web_push_message = match web_push_message_build.build() {
-                        Ok(message) => message,
-                        Err(e) => {
-                                tracing::error!("Failed to build web push message: {:?}", e);
-                                continue;
-                        }
-                };
+pub async fn mark_group_read(
+        ctx: RequestContext,
+        group_id: String,
+        message_id: String,
+) -> Result<ReceiptResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let message_id: i64 = message_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid message_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        if !ctx.app_state
+                .message_repository
+                .exists_by_id_and_group_id(message_id, group.id)?
+        {
+                return Err(Error::new(ErrorCode::NotFound, "Message not found".into()));
+        }
+
+        let previous_last_read_message_id = group
+                .users
+                .iter()
+                .find(|gu| gu.user.id == auth_user.id)
+                .and_then(|gu| gu.last_read_message_id);
 
-                match client.send(web_push_message).await {
-                        Ok(_) => tracing::info!("Web push notification sent successfully"),
-                        Err(e) => tracing::error!("Failed to send web push message: {:?}", e),
+        let group_user =
+                ctx.app_state
+                        .group_repository
+                        .update_last_read_message_id(group.id, auth_user.id, message_id)?;
+
+        if group_user.last_read_message_id != previous_last_read_message_id {
+                for gu in group.users.iter().filter(|gu| gu.user.id != auth_user.id) {
+                        ctx.app_state
+                                .send_event(
+                                        gu.user.id,
+                                        MessageEventDto::Receipt(ReceiptResponseDto::from(group_user.clone())),
+                                )
+                                .await;
                 }
         }
+
+        let receipt_response = ReceiptResponseDto::from(group_user);
+
+        Ok(receipt_response)
 }
 
-pub async fn create_group_message(
+pub async fn mark_group_unread(ctx: RequestContext, group_id: String) -> Result<UnreadResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let group_user = ctx
+                .app_state
+                .group_repository
+                .update_marked_unread(group.id, auth_user.id, true)?;
+
+        let unread_count = ctx
+                .app_state
+                .message_repository
+                .count_unread_by_user_id(auth_user.id, vec![group.id])?
+                .remove(&group.id)
+                .unwrap_or(0);
+
+        let unread_response = UnreadResponseDto::from((group_user, unread_count));
+
+        Ok(unread_response)
+}
+
+fn update_group_settings(
+        ctx: &RequestContext,
+        group: &GroupWithRelationships,
+        user_id: i64,
+        update: impl FnOnce(&mut GroupUserSettings),
+) -> Result<GroupSettingsResponseDto, Error> {
+        let group_user = group
+                .users
+                .iter()
+                .find(|gu| gu.user.id == user_id)
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let mut group_user_settings = GroupUserSettings {
+                updated_at: Utc::now().naive_utc(),
+                muted_until: group_user.muted_until,
+                archived: group_user.archived,
+                pinned: group_user.pinned,
+        };
+        update(&mut group_user_settings);
+
+        let group_user = ctx
+                .app_state
+                .group_repository
+                .update_settings(group.id, user_id, group_user_settings)?;
+
+        Ok(GroupSettingsResponseDto::from(group_user))
+}
+
+pub async fn set_group_muted(
         ctx: RequestContext,
         group_id: String,
-        message_request: MessageRequestDto,
-) -> Result<MessageResponseDto, Error> {
+        muted_until: Option<chrono::NaiveDateTime>,
+) -> Result<GroupSettingsResponseDto, Error> {
         let group_id: i64 = group_id
                 .parse()
                 .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
@@ -181,64 +1673,144 @@ pub async fn create_group_message(
                 .find_by_id_and_user_id(group_id, auth_user.id)?
                 .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
 
-        let message = {
-                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
-                ctx.app_state.message_repository.save(MessageWithRelationships {
-                        id: id_generator.generate(),
-                        created_at: Utc::now().naive_utc(),
-                        updated_at: Utc::now().naive_utc(),
-                        group: group.clone(),
-                        source: auth_user.clone(),
-                        content: message_request
-                                .content
-                                .clone()
-                                .into_iter()
-                                .filter_map(|(k, v)| k.parse::<i64>().ok().map(|key| (key, v)))
-                                .collect(),
-                        idempotency_key: message_request.idempotency_key.clone(),
-                })?
+        update_group_settings(&ctx, &group, auth_user.id, |settings| {
+                settings.muted_until = muted_until
+        })
+}
+
+pub async fn set_group_archived(
+        ctx: RequestContext,
+        group_id: String,
+        archived: bool,
+) -> Result<GroupSettingsResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        update_group_settings(&ctx, &group, auth_user.id, |settings| settings.archived = archived)
+}
+
+pub async fn set_group_pinned(
+        ctx: RequestContext,
+        group_id: String,
+        pinned: bool,
+) -> Result<GroupSettingsResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        update_group_settings(&ctx, &group, auth_user.id, |settings| settings.pinned = pinned)
+}
+
+async fn broadcast_typing(app_state: &AppState, group: &GroupWithRelationships, typing_response: TypingResponseDto) {
+        for gu in group
+                .users
+                .iter()
+                .filter(|gu| gu.user.id.to_string() != typing_response.user_id)
+        {
+                app_state
+                        .send_event(gu.user.id, MessageEventDto::Typing(typing_response.clone()))
+                        .await;
+        }
+}
+
+pub async fn start_typing(ctx: RequestContext, group_id: String) -> Result<TypingResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        let started_at = Instant::now();
+        ctx.app_state
+                .typing_expirations
+                .write()
+                .await
+                .insert((group.id, auth_user.id), started_at);
+
+        let typing_response = TypingResponseDto {
+                group_id: group.id.to_string(),
+                user_id: auth_user.id.to_string(),
+                typing: true,
+                expires_at: Some(Utc::now().naive_utc() + TYPING_TIMEOUT),
         };
 
-        for gu in group.users.iter() {
-                tracing::debug!("Looking for user {} in message senders", gu.user.id);
-                if let Some(sender) = ctx.app_state.message_senders.read().await.get(&gu.user.id) {
-                        tracing::debug!("Sending message to user {}", gu.user.id);
-                        let message_response = MessageWithGroupResponseDto {
-                                id: message.id.to_string(),
-                                created_at: message.created_at,
-                                updated_at: message.updated_at,
-                                group: GroupResponseDto::from(message.group.clone()),
-                                source: UserResponseDto::from(message.source.clone()),
-                                content: message.content.get(&gu.user.id).unwrap().clone(),
-                                idempotency_key: message.idempotency_key.clone(),
-                        };
-                        match sender.send(message_response) {
-                                Ok(_) => {
-                                        tracing::debug!("Message sent to user {}", gu.user.id);
-                                }
-                                Err(e) => {
-                                        tracing::error!("Failed to send message to user: {:?}", e);
-                                }
+        broadcast_typing(&ctx.app_state, &group, typing_response.clone()).await;
+
+        let app_state = Arc::clone(&ctx.app_state);
+        tokio::spawn(async move {
+                tokio::time::sleep(TYPING_TIMEOUT).await;
+
+                {
+                        let mut typing_expirations = app_state.typing_expirations.write().await;
+                        if typing_expirations.get(&(group.id, auth_user.id)) != Some(&started_at) {
+                                return;
                         }
+                        typing_expirations.remove(&(group.id, auth_user.id));
+                }
+
+                let typing_response = TypingResponseDto {
+                        group_id: group.id.to_string(),
+                        user_id: auth_user.id.to_string(),
+                        typing: false,
+                        expires_at: None,
                 };
-        }
 
-        let message_for_notification = message.clone();
+                broadcast_typing(&app_state, &group, typing_response).await;
+        });
 
-        send_web_push_notifications(&ctx, group, message_for_notification).await;
+        Ok(typing_response)
+}
+
+pub async fn stop_typing(ctx: RequestContext, group_id: String) -> Result<TypingResponseDto, Error> {
+        let group_id: i64 = group_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid group_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let group = ctx
+                .app_state
+                .group_repository
+                .find_by_id_and_user_id(group_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Group not found".into()))?;
+
+        ctx.app_state
+                .typing_expirations
+                .write()
+                .await
+                .remove(&(group.id, auth_user.id));
 
-        let message_response = MessageResponseDto {
-                id: message.id.to_string(),
-                created_at: message.created_at,
-                updated_at: message.updated_at,
-                source: UserResponseDto::from(message.source),
-                content: message
-                        .content
-                        .get(&auth_user.id)
-                        .expect("Got message not for auth user")
-                        .clone(),
-                idempotency_key: message.idempotency_key,
+        let typing_response = TypingResponseDto {
+                group_id: group.id.to_string(),
+                user_id: auth_user.id.to_string(),
+                typing: false,
+                expires_at: None,
         };
 
-        Ok(message_response)
+        broadcast_typing(&ctx.app_state, &group, typing_response.clone()).await;
+
+        Ok(typing_response)
 }
@@ -1,27 +1,100 @@
 use rspc::Error;
 
 use crate::{
-        dtos::{GroupResponseDto, MessageWithGroupResponseDto, UserResponseDto},
+        dtos::{
+                aggregate_reactions, receipt_status, ConversationResponseDto, GroupResponseDto,
+                GroupSettingsResponseDto, InboxRequestDto, MessageReplyResponseDto, MessageWithGroupResponseDto,
+                UnreadResponseDto, UserResponseDto,
+        },
         RequestContext,
 };
 
-pub async fn get_messages(ctx: RequestContext) -> Result<Vec<MessageWithGroupResponseDto>, Error> {
+pub async fn get_messages(
+        ctx: RequestContext,
+        inbox_request: Option<InboxRequestDto>,
+) -> Result<Vec<ConversationResponseDto>, Error> {
         let auth_user = ctx.get_auth_user().await?;
 
         let messages = ctx.app_state.message_repository.find_by_user_id(auth_user.id)?;
 
-        let message_responses = messages
+        let unread_counts = ctx
+                .app_state
+                .message_repository
+                .count_unread_by_user_id(auth_user.id, messages.iter().map(|message| message.group.id).collect())?;
+
+        let include_archived = inbox_request
+                .and_then(|inbox_request| inbox_request.include_archived)
+                .unwrap_or(false);
+
+        let mut messages = messages
+                .into_iter()
+                .filter(|message| {
+                        message.group
+                                .users
+                                .iter()
+                                .find(|gu| gu.user.id == auth_user.id)
+                                .is_some_and(|gu| include_archived || !gu.archived)
+                })
+                .collect::<Vec<_>>();
+
+        // Pinned conversations first, then most recent activity first
+        messages.sort_by_key(|message| {
+                let pinned = message
+                        .group
+                        .users
+                        .iter()
+                        .any(|gu| gu.user.id == auth_user.id && gu.pinned);
+                (!pinned, std::cmp::Reverse(message.id))
+        });
+
+        let conversation_responses = messages
                 .into_iter()
-                .map(|message| MessageWithGroupResponseDto {
-                        id: message.id.to_string(),
-                        created_at: message.created_at,
-                        updated_at: message.updated_at,
-                        group: GroupResponseDto::from(message.group),
-                        source: UserResponseDto::from(message.source),
-                        content: message.content,
-                        idempotency_key: message.idempotency_key,
+                .filter_map(|message| {
+                        let group_user = message.group.users.iter().find(|gu| gu.user.id == auth_user.id)?;
+
+                        let unread = UnreadResponseDto {
+                                group_id: message.group.id.to_string(),
+                                unread_count: unread_counts.get(&message.group.id).copied().unwrap_or(0) as i32,
+                                last_read_message_id: group_user.last_read_message_id.map(|id| id.to_string()),
+                                marked_unread: group_user.marked_unread,
+                        };
+
+                        let settings = GroupSettingsResponseDto {
+                                group_id: message.group.id.to_string(),
+                                muted_until: group_user.muted_until,
+                                archived: group_user.archived,
+                                pinned: group_user.pinned,
+                        };
+
+                        let receipt = receipt_status(&message.group.users, message.id, message.source.id, auth_user.id);
+
+                        let message = MessageWithGroupResponseDto {
+                                id: message.id.to_string(),
+                                created_at: message.created_at,
+                                updated_at: message.updated_at,
+                                group: GroupResponseDto::from((message.group, auth_user.id)),
+                                source: UserResponseDto::from(message.source),
+                                content: Some(message.content),
+                                idempotency_key: message.idempotency_key,
+                                edited_at: message.edited_at,
+                                deleted_at: message.deleted_at,
+                                reactions: aggregate_reactions(&message.reactions, auth_user.id),
+                                reply_to: message
+                                        .reply_to
+                                        .map(|reply_to| MessageReplyResponseDto::from((reply_to, auth_user.id))),
+                                receipt,
+                                kind: message.kind,
+                                system_event: message.system_event,
+                                expires_at: message.expires_at,
+                        };
+
+                        Some(ConversationResponseDto {
+                                message,
+                                unread,
+                                settings,
+                        })
                 })
-                .collect::<Vec<MessageWithGroupResponseDto>>();
+                .collect::<Vec<ConversationResponseDto>>();
 
-        Ok(message_responses)
+        Ok(conversation_responses)
 }
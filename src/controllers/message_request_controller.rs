@@ -1,15 +1,58 @@
-use chrono::Utc;
+use std::env;
+
+use chrono::{Duration, Utc};
 use rspc::{Error, ErrorCode};
 
 use crate::{
-        dtos::{MessageRequestRequestDto, MessageRequestResponseDto},
-        models::{GroupUserWithRelationships, GroupWithRelationships, MessageRequestWithRelationships},
+        controllers::group_controller,
+        dtos::{
+                MessageEventDto, MessageRequestPageRequestDto, MessageRequestPageResponseDto, MessageRequestRequestDto,
+                MessageRequestResponseDto,
+        },
+        models::{
+                GroupRole, GroupUserWithRelationships, GroupWithRelationships, MessageRequestStatus,
+                MessageRequestWithRelationships, SystemEvent, User,
+        },
         RequestContext,
 };
 
-pub async fn get_message_request(
+const DEFAULT_PAGE_LIMIT: i32 = 50;
+const MAX_PAGE_LIMIT: i32 = 100;
+const DEFAULT_DECLINE_COOLDOWN_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+fn decline_cooldown() -> Duration {
+        let seconds = env::var("MESSAGE_REQUEST_DECLINE_COOLDOWN_SECONDS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .unwrap_or(DEFAULT_DECLINE_COOLDOWN_SECONDS);
+
+        Duration::seconds(seconds)
+}
+
+/// Delivers a request event to the user's live stream and, as with new messages, to their push subscription.
+async fn notify_message_request(
+        ctx: &RequestContext,
+        user_id: i64,
+        message_request_response: MessageRequestResponseDto,
+) {
+        let event = MessageEventDto::MessageRequest(message_request_response);
+
+        let json_event = match serde_json::to_string(&event) {
+                Ok(json) => json,
+                Err(e) => {
+                        tracing::error!("Failed to serialize message request event: {:?}", e);
+                        return;
+                }
+        };
+
+        ctx.app_state.send_event(user_id, event).await;
+        group_controller::send_web_push_notification(ctx, user_id, &json_event).await;
+}
+
+async fn respond_to_message_request(
         ctx: RequestContext,
         message_request_id: String,
+        status: MessageRequestStatus,
 ) -> Result<MessageRequestResponseDto, Error> {
         let message_request_id: i64 = message_request_id
                 .parse()
@@ -23,11 +66,234 @@ pub async fn get_message_request(
                 .find_by_id_and_destination_id(message_request_id, auth_user.id)?
                 .ok_or(Error::new(ErrorCode::NotFound, "Message request not found".into()))?;
 
+        if !ctx.app_state
+                .message_request_repository
+                .update_status_if_pending(message_request.id, status)?
+        {
+                return Err(Error::new(ErrorCode::Conflict, "Message request is not pending".into()));
+        }
+
+        let message_request = ctx
+                .app_state
+                .message_request_repository
+                .find_by_id_and_destination_id(message_request.id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message request not found".into()))?;
+
+        let message_request_response = MessageRequestResponseDto::from(message_request);
+
+        Ok(message_request_response)
+}
+
+fn find_message_request_page(
+        ctx: &RequestContext,
+        user_id: i64,
+        incoming: bool,
+        page_request: MessageRequestPageRequestDto,
+) -> Result<MessageRequestPageResponseDto, Error> {
+        let before: Option<i64> = page_request
+                .before
+                .map(|before| before.parse())
+                .transpose()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid before".into()))?;
+
+        let limit = page_request
+                .limit
+                .unwrap_or(DEFAULT_PAGE_LIMIT)
+                .clamp(1, MAX_PAGE_LIMIT) as i64;
+
+        let mut message_requests = if incoming {
+                ctx.app_state.message_request_repository.find_by_destination_id(
+                        user_id,
+                        page_request.status,
+                        before,
+                        limit + 1,
+                )?
+        } else {
+                ctx.app_state.message_request_repository.find_by_source_id(
+                        user_id,
+                        page_request.status,
+                        before,
+                        limit + 1,
+                )?
+        };
+
+        let has_more = message_requests.len() as i64 > limit;
+        message_requests.truncate(limit as usize);
+
+        let next_cursor = if has_more {
+                message_requests
+                        .last()
+                        .map(|message_request| message_request.id.to_string())
+        } else {
+                None
+        };
+
+        let message_request_page_response = MessageRequestPageResponseDto {
+                message_requests: message_requests
+                        .into_iter()
+                        .map(MessageRequestResponseDto::from)
+                        .collect(),
+                has_more,
+                next_cursor,
+        };
+
+        Ok(message_request_page_response)
+}
+
+pub async fn get_message_request(
+        ctx: RequestContext,
+        message_request_id: String,
+) -> Result<MessageRequestResponseDto, Error> {
+        let message_request_id: i64 = message_request_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid message_request_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let message_request = match ctx
+                .app_state
+                .message_request_repository
+                .find_by_id_and_destination_id(message_request_id, auth_user.id)?
+        {
+                Some(message_request) => message_request,
+                None => ctx
+                        .app_state
+                        .message_request_repository
+                        .find_by_id_and_source_id(message_request_id, auth_user.id)?
+                        .ok_or(Error::new(ErrorCode::NotFound, "Message request not found".into()))?,
+        };
+
         let message_request_response = MessageRequestResponseDto::from(message_request);
 
         Ok(message_request_response)
 }
 
+async fn approve(
+        ctx: &RequestContext,
+        auth_user: &User,
+        message_request: MessageRequestWithRelationships,
+) -> Result<MessageRequestWithRelationships, Error> {
+        match message_request.status {
+                MessageRequestStatus::Pending => {}
+                MessageRequestStatus::Approved => return Ok(message_request),
+                _ => return Err(Error::new(ErrorCode::Conflict, "Message request is not pending".into())),
+        }
+
+        let group = {
+                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
+                GroupWithRelationships {
+                        id: id_generator.generate(),
+                        created_at: Utc::now().naive_utc(),
+                        updated_at: Utc::now().naive_utc(),
+                        name: None,
+                        message_request_id: Some(message_request.id),
+                        archived_at: None,
+                        avatar_url: None,
+                        message_ttl_seconds: None,
+                        users: vec![
+                                GroupUserWithRelationships {
+                                        id: id_generator.generate(),
+                                        created_at: Utc::now().naive_utc(),
+                                        updated_at: Utc::now().naive_utc(),
+                                        user: message_request.source.clone(),
+                                        nickname: None,
+                                        last_delivered_message_id: None,
+                                        last_read_message_id: None,
+                                        marked_unread: false,
+                                        role: GroupRole::Owner,
+                                        muted_until: None,
+                                        archived: false,
+                                        pinned: false,
+                                },
+                                GroupUserWithRelationships {
+                                        id: id_generator.generate(),
+                                        created_at: Utc::now().naive_utc(),
+                                        updated_at: Utc::now().naive_utc(),
+                                        user: message_request.destination.clone(),
+                                        nickname: None,
+                                        last_delivered_message_id: None,
+                                        last_read_message_id: None,
+                                        marked_unread: false,
+                                        role: GroupRole::Owner,
+                                        muted_until: None,
+                                        archived: false,
+                                        pinned: false,
+                                },
+                        ],
+                }
+        };
+        let approved = ctx
+                .app_state
+                .message_request_repository
+                .approve(message_request.id, group.clone())?;
+
+        let message_request = ctx
+                .app_state
+                .message_request_repository
+                .find_by_id_and_destination_id(message_request.id, message_request.destination.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message request not found".into()))?;
+
+        // Lost a race with a concurrent approval, decline or cancel of the same request
+        if !approved {
+                return match message_request.status {
+                        MessageRequestStatus::Approved => Ok(message_request),
+                        _ => Err(Error::new(ErrorCode::Conflict, "Message request is not pending".into())),
+                };
+        }
+
+        let system_event = SystemEvent::MessageRequestApproved {
+                user_ids: vec![
+                        message_request.source.id.to_string(),
+                        message_request.destination.id.to_string(),
+                ],
+        };
+        group_controller::create_system_message(ctx, &group, auth_user, system_event).await;
+
+        notify_message_request(
+                ctx,
+                message_request.source.id,
+                MessageRequestResponseDto::from(message_request.clone()),
+        )
+        .await;
+
+        Ok(message_request)
+}
+
+/// Returns the open request between the two users, approving it when the other user had already asked.
+async fn resolve_existing_message_request(
+        ctx: &RequestContext,
+        auth_user: &User,
+        message_request: MessageRequestWithRelationships,
+) -> Result<MessageRequestResponseDto, Error> {
+        let message_request = if message_request.status == MessageRequestStatus::Pending
+                && message_request.destination.id == auth_user.id
+        {
+                approve(ctx, auth_user, message_request).await?
+        } else {
+                message_request
+        };
+
+        Ok(MessageRequestResponseDto::from(message_request))
+}
+
+pub async fn list_incoming_message_requests(
+        ctx: RequestContext,
+        page_request: MessageRequestPageRequestDto,
+) -> Result<MessageRequestPageResponseDto, Error> {
+        let auth_user = ctx.get_auth_user().await?;
+
+        find_message_request_page(&ctx, auth_user.id, true, page_request)
+}
+
+pub async fn list_outgoing_message_requests(
+        ctx: RequestContext,
+        page_request: MessageRequestPageRequestDto,
+) -> Result<MessageRequestPageResponseDto, Error> {
+        let auth_user = ctx.get_auth_user().await?;
+
+        find_message_request_page(&ctx, auth_user.id, false, page_request)
+}
+
 pub async fn create_message_request(
         ctx: RequestContext,
         message_request_request: MessageRequestRequestDto,
@@ -40,13 +306,42 @@ pub async fn create_message_request(
                 .parse()
                 .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid destinationId".into()))?;
 
+        if destination_id == auth_user.id {
+                return Err(Error::new(
+                        ErrorCode::BadRequest,
+                        "Cannot send a message request to yourself".into(),
+                ));
+        }
+
         let destination = ctx
                 .app_state
                 .user_repository
                 .find_by_id(destination_id)?
                 .ok_or(Error::new(ErrorCode::NotFound, "Destination user not found".into()))?;
 
-        let message_request = {
+        if let Some(message_request) = ctx
+                .app_state
+                .message_request_repository
+                .find_open_by_user_ids(auth_user.id, destination.id)?
+        {
+                return resolve_existing_message_request(&ctx, &auth_user, message_request).await;
+        }
+
+        let last_declined_at = ctx
+                .app_state
+                .message_request_repository
+                .find_last_declined_at_by_source_id_and_destination_id(auth_user.id, destination.id)?;
+
+        if last_declined_at
+                .is_some_and(|last_declined_at| last_declined_at + decline_cooldown() > Utc::now().naive_utc())
+        {
+                return Err(Error::new(
+                        ErrorCode::Forbidden,
+                        "Message request was declined recently".into(),
+                ));
+        }
+
+        let saved_message_request = {
                 let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
                 ctx.app_state
                         .message_request_repository
@@ -57,11 +352,32 @@ pub async fn create_message_request(
                                 source: auth_user.clone(),
                                 destination: destination.clone(),
                                 approved_at: None,
-                        })?
+                                status: MessageRequestStatus::Pending,
+                                group_id: None,
+                        })
+        };
+
+        let message_request = match saved_message_request {
+                Ok(message_request) => message_request,
+                Err(error) => {
+                        // A concurrent request between the same two users won the unique pending index.
+                        return match ctx
+                                .app_state
+                                .message_request_repository
+                                .find_open_by_user_ids(auth_user.id, destination.id)?
+                        {
+                                Some(message_request) => {
+                                        resolve_existing_message_request(&ctx, &auth_user, message_request).await
+                                }
+                                None => Err(error),
+                        };
+                }
         };
 
         let message_request_response = MessageRequestResponseDto::from(message_request);
 
+        notify_message_request(&ctx, destination.id, message_request_response.clone()).await;
+
         Ok(message_request_response)
 }
 
@@ -75,45 +391,62 @@ pub async fn approve_message_request(
 
         let auth_user = ctx.get_auth_user().await?;
 
-        let mut message_request = ctx
+        let message_request = ctx
                 .app_state
                 .message_request_repository
                 .find_by_id_and_destination_id(message_request_id, auth_user.id)?
                 .ok_or(Error::new(ErrorCode::NotFound, "Message request not found".into()))?;
 
-        message_request.updated_at = Utc::now().naive_utc();
-        message_request.approved_at = Some(Utc::now().naive_utc());
-        let message_request = ctx.app_state.message_request_repository.save(message_request)?;
+        let message_request = approve(&ctx, &auth_user, message_request).await?;
 
+        let message_request_response = MessageRequestResponseDto::from(message_request);
+
+        Ok(message_request_response)
+}
+
+pub async fn decline_message_request(
+        ctx: RequestContext,
+        message_request_id: String,
+) -> Result<MessageRequestResponseDto, Error> {
+        respond_to_message_request(ctx, message_request_id, MessageRequestStatus::Declined).await
+}
+
+pub async fn ignore_message_request(
+        ctx: RequestContext,
+        message_request_id: String,
+) -> Result<MessageRequestResponseDto, Error> {
+        respond_to_message_request(ctx, message_request_id, MessageRequestStatus::Ignored).await
+}
+
+pub async fn cancel_message_request(
+        ctx: RequestContext,
+        message_request_id: String,
+) -> Result<MessageRequestResponseDto, Error> {
+        let message_request_id: i64 = message_request_id
+                .parse()
+                .map_err(|_| Error::new(ErrorCode::BadRequest, "Invalid message_request_id".into()))?;
+
+        let auth_user = ctx.get_auth_user().await?;
+
+        let message_request = ctx
+                .app_state
+                .message_request_repository
+                .find_by_id_and_source_id(message_request_id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message request not found".into()))?;
+
+        if !ctx.app_state
+                .message_request_repository
+                .update_status_if_pending(message_request.id, MessageRequestStatus::Cancelled)?
         {
-                let mut id_generator = ctx.app_state.id_generator.lock().unwrap();
-                ctx.app_state.group_repository.save(GroupWithRelationships {
-                        id: id_generator.generate(),
-                        created_at: Utc::now().naive_utc(),
-                        updated_at: Utc::now().naive_utc(),
-                        name: None,
-                        message_request_id: Some(message_request.id),
-                        users: vec![
-                                GroupUserWithRelationships {
-                                        id: id_generator.generate(),
-                                        created_at: Utc::now().naive_utc(),
-                                        updated_at: Utc::now().naive_utc(),
-                                        user: message_request.source.clone(),
-                                        is_admin: true,
-                                        nickname: None,
-                                },
-                                GroupUserWithRelationships {
-                                        id: id_generator.generate(),
-                                        created_at: Utc::now().naive_utc(),
-                                        updated_at: Utc::now().naive_utc(),
-                                        user: message_request.destination.clone(),
-                                        is_admin: true,
-                                        nickname: None,
-                                },
-                        ],
-                })?;
+                return Err(Error::new(ErrorCode::Conflict, "Message request is not pending".into()));
         }
 
+        let message_request = ctx
+                .app_state
+                .message_request_repository
+                .find_by_id_and_source_id(message_request.id, auth_user.id)?
+                .ok_or(Error::new(ErrorCode::NotFound, "Message request not found".into()))?;
+
         let message_request_response = MessageRequestResponseDto::from(message_request);
 
         Ok(message_request_response)
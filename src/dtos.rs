@@ -2,7 +2,11 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use std::collections::HashMap;
 
-use crate::models::{GroupWithRelationships, MessageRequestWithRelationships, User};
+use crate::models::{
+        GroupInvite, GroupRole, GroupUser, GroupUserWithRelationships, GroupWithRelationships, MessageKind,
+        MessageReaction, MessageReply, MessageRequestStatus, MessageRequestWithRelationships, MessageWithRelationships,
+        MessageWithSource, SystemEvent, User,
+};
 
 #[derive(Type, Clone, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -29,9 +33,7 @@ impl From<User> for UserResponseDto {
                         id: user.id.to_string(),
                         created_at: user.created_at,
                         updated_at: user.updated_at,
-                        name: user
-                                .display_name
-                                .unwrap_or(vec![user.first_name, user.last_name].join(" ")),
+                        name: user.display_name.unwrap_or([user.first_name, user.last_name].join(" ")),
                         public_key: user.public_key,
                 }
         }
@@ -52,6 +54,8 @@ pub struct MessageRequestResponseDto {
         pub source: UserResponseDto,
         pub destination: UserResponseDto,
         pub approved_at: Option<chrono::NaiveDateTime>,
+        pub status: MessageRequestStatus,
+        pub group_id: Option<String>,
 }
 
 impl From<MessageRequestWithRelationships> for MessageRequestResponseDto {
@@ -63,6 +67,60 @@ impl From<MessageRequestWithRelationships> for MessageRequestResponseDto {
                         source: UserResponseDto::from(message_request.source),
                         destination: UserResponseDto::from(message_request.destination),
                         approved_at: message_request.approved_at,
+                        status: message_request.status,
+                        group_id: message_request.group_id.map(|group_id| group_id.to_string()),
+                }
+        }
+}
+
+#[derive(Type, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupRequestDto {
+        pub name: String,
+        pub user_ids: Vec<String>,
+}
+
+#[derive(Type, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMembersRequestDto {
+        pub user_ids: Vec<String>,
+}
+
+#[derive(Type, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupInviteRequestDto {
+        pub expires_at: chrono::NaiveDateTime,
+        pub max_uses: Option<i32>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupInviteResponseDto {
+        pub id: String,
+        pub created_at: chrono::NaiveDateTime,
+        pub updated_at: chrono::NaiveDateTime,
+        pub group_id: String,
+        pub source_id: String,
+        pub token: String,
+        pub expires_at: chrono::NaiveDateTime,
+        pub max_uses: Option<i32>,
+        pub use_count: i32,
+        pub revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<GroupInvite> for GroupInviteResponseDto {
+        fn from(group_invite: GroupInvite) -> Self {
+                GroupInviteResponseDto {
+                        id: group_invite.id.to_string(),
+                        created_at: group_invite.created_at,
+                        updated_at: group_invite.updated_at,
+                        group_id: group_invite.group_id.to_string(),
+                        source_id: group_invite.source_id.to_string(),
+                        token: group_invite.token,
+                        expires_at: group_invite.expires_at,
+                        max_uses: group_invite.max_uses,
+                        use_count: group_invite.use_count,
+                        revoked_at: group_invite.revoked_at,
                 }
         }
 }
@@ -76,10 +134,15 @@ pub struct GroupResponseDto {
         pub name: String,
         pub message_request_id: Option<String>,
         pub users: Vec<UserResponseDto>,
+        pub role: Option<GroupRole>,
+        pub avatar_url: Option<String>,
+        pub message_ttl_seconds: Option<i32>,
 }
 
-impl From<GroupWithRelationships> for GroupResponseDto {
-        fn from(group: GroupWithRelationships) -> Self {
+impl From<(GroupWithRelationships, i64)> for GroupResponseDto {
+        fn from((group, user_id): (GroupWithRelationships, i64)) -> Self {
+                let role = group.users.iter().find(|gu| gu.user.id == user_id).map(|gu| gu.role);
+
                 GroupResponseDto {
                         id: group.id.to_string(),
                         created_at: group.created_at,
@@ -93,7 +156,7 @@ impl From<GroupWithRelationships> for GroupResponseDto {
                         }),
                         message_request_id: group
                                 .message_request_id
-                                .map_or(None, |message_request_id| Some(message_request_id.to_string())),
+                                .map(|message_request_id| message_request_id.to_string()),
                         users: group
                                 .users
                                 .iter()
@@ -106,6 +169,9 @@ impl From<GroupWithRelationships> for GroupResponseDto {
                                         user
                                 })
                                 .collect(),
+                        role,
+                        avatar_url: group.avatar_url,
+                        message_ttl_seconds: group.message_ttl_seconds,
                 }
         }
 }
@@ -115,6 +181,122 @@ impl From<GroupWithRelationships> for GroupResponseDto {
 pub struct MessageRequestDto {
         pub content: HashMap<String, String>,
         pub idempotency_key: Option<String>,
+        pub reply_to_id: Option<String>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReactionResponseDto {
+        pub emoji: String,
+        pub count: i32,
+        pub reacted: bool,
+}
+
+pub fn aggregate_reactions(reactions: &[MessageReaction], user_id: i64) -> Vec<MessageReactionResponseDto> {
+        reactions
+                .iter()
+                .fold(Vec::<MessageReactionResponseDto>::new(), |mut acc, reaction| {
+                        match acc.iter_mut().find(|r| r.emoji == reaction.emoji) {
+                                Some(r) => {
+                                        r.count += 1;
+                                        r.reacted |= reaction.user_id == user_id;
+                                }
+                                None => acc.push(MessageReactionResponseDto {
+                                        emoji: reaction.emoji.clone(),
+                                        count: 1,
+                                        reacted: reaction.user_id == user_id,
+                                }),
+                        }
+                        acc
+                })
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReplyResponseDto {
+        pub id: String,
+        pub created_at: chrono::NaiveDateTime,
+        pub source: UserResponseDto,
+        pub content: Option<String>,
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<(MessageReply, i64)> for MessageReplyResponseDto {
+        fn from((message_reply, user_id): (MessageReply, i64)) -> Self {
+                MessageReplyResponseDto {
+                        id: message_reply.id.to_string(),
+                        created_at: message_reply.created_at,
+                        source: UserResponseDto::from(message_reply.source),
+                        content: message_reply.content.get(&user_id).cloned(),
+                        deleted_at: message_reply.deleted_at,
+                }
+        }
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptStatusResponseDto {
+        pub recipient_count: i32,
+        pub delivered_count: i32,
+        pub read_count: i32,
+}
+
+pub fn receipt_status(
+        group_users: &[GroupUserWithRelationships],
+        message_id: i64,
+        source_id: i64,
+        user_id: i64,
+) -> Option<ReceiptStatusResponseDto> {
+        if source_id != user_id {
+                return None;
+        }
+
+        let recipients = group_users.iter().filter(|gu| gu.user.id != source_id);
+
+        Some(ReceiptStatusResponseDto {
+                recipient_count: recipients.clone().count() as i32,
+                delivered_count: recipients
+                        .clone()
+                        .filter(|gu| gu.last_delivered_message_id.is_some_and(|id| id >= message_id))
+                        .count() as i32,
+                read_count: recipients
+                        .filter(|gu| gu.last_read_message_id.is_some_and(|id| id >= message_id))
+                        .count() as i32,
+        })
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptResponseDto {
+        pub group_id: String,
+        pub user_id: String,
+        pub last_delivered_message_id: Option<String>,
+        pub last_read_message_id: Option<String>,
+}
+
+impl From<GroupUser> for ReceiptResponseDto {
+        fn from(group_user: GroupUser) -> Self {
+                ReceiptResponseDto {
+                        group_id: group_user.group_id.to_string(),
+                        user_id: group_user.user_id.to_string(),
+                        last_delivered_message_id: group_user.last_delivered_message_id.map(|id| id.to_string()),
+                        last_read_message_id: group_user.last_read_message_id.map(|id| id.to_string()),
+                }
+        }
+}
+
+#[derive(Type, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageEditRequestDto {
+        pub content: HashMap<String, String>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentValidationErrorDto {
+        pub missing_user_ids: Vec<String>,
+        pub unexpected_user_ids: Vec<String>,
+        pub malformed_user_ids: Vec<String>,
 }
 
 #[derive(Type, Serialize, Debug, Clone)]
@@ -124,8 +306,108 @@ pub struct MessageResponseDto {
         pub created_at: chrono::NaiveDateTime,
         pub updated_at: chrono::NaiveDateTime,
         pub source: UserResponseDto,
-        pub content: String,
+        pub content: Option<String>,
         pub idempotency_key: Option<String>,
+        pub edited_at: Option<chrono::NaiveDateTime>,
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+        pub reactions: Vec<MessageReactionResponseDto>,
+        pub reply_to: Option<MessageReplyResponseDto>,
+        pub receipt: Option<ReceiptStatusResponseDto>,
+        pub kind: MessageKind,
+        pub system_event: Option<SystemEvent>,
+        pub expires_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<(MessageWithSource, i64)> for MessageResponseDto {
+        fn from((message, user_id): (MessageWithSource, i64)) -> Self {
+                MessageResponseDto {
+                        id: message.id.to_string(),
+                        created_at: message.created_at,
+                        updated_at: message.updated_at,
+                        source: UserResponseDto::from(message.source),
+                        content: message.content,
+                        idempotency_key: message.idempotency_key,
+                        edited_at: message.edited_at,
+                        deleted_at: message.deleted_at,
+                        reactions: aggregate_reactions(&message.reactions, user_id),
+                        reply_to: message
+                                .reply_to
+                                .map(|reply_to| MessageReplyResponseDto::from((reply_to, user_id))),
+                        receipt: None,
+                        kind: message.kind,
+                        system_event: message.system_event,
+                        expires_at: message.expires_at,
+                }
+        }
+}
+
+impl From<(MessageWithRelationships, i64)> for MessageResponseDto {
+        fn from((message, user_id): (MessageWithRelationships, i64)) -> Self {
+                let receipt = receipt_status(&message.group.users, message.id, message.source.id, user_id);
+
+                MessageResponseDto {
+                        id: message.id.to_string(),
+                        created_at: message.created_at,
+                        updated_at: message.updated_at,
+                        source: UserResponseDto::from(message.source),
+                        content: message.content.get(&user_id).cloned(),
+                        idempotency_key: message.idempotency_key,
+                        edited_at: message.edited_at,
+                        deleted_at: message.deleted_at,
+                        reactions: aggregate_reactions(&message.reactions, user_id),
+                        reply_to: message
+                                .reply_to
+                                .map(|reply_to| MessageReplyResponseDto::from((reply_to, user_id))),
+                        receipt,
+                        kind: message.kind,
+                        system_event: message.system_event,
+                        expires_at: message.expires_at,
+                }
+        }
+}
+
+#[derive(Type, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PageRequestDto {
+        /// Pages backwards through history, returning messages older than this id newest-first.
+        /// This is also the order used when neither cursor is set.
+        pub before: Option<String>,
+        /// Pages forwards to catch up, returning messages newer than this id oldest-first.
+        pub after: Option<String>,
+        pub limit: Option<i32>,
+}
+
+#[derive(Type, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRequestPageRequestDto {
+        pub status: Option<MessageRequestStatus>,
+        pub before: Option<String>,
+        pub limit: Option<i32>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRequestPageResponseDto {
+        pub message_requests: Vec<MessageRequestResponseDto>,
+        pub has_more: bool,
+        pub next_cursor: Option<String>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagePageResponseDto {
+        pub messages: Vec<MessageResponseDto>,
+        pub has_more: bool,
+        /// The last message id in the page. Pass it back as the same cursor the page was requested with:
+        /// `before` for newest-first pages and `after` for oldest-first ones.
+        pub next_cursor: Option<String>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadResponseDto {
+        pub root: MessageResponseDto,
+        pub replies: MessagePageResponseDto,
 }
 
 #[derive(Type, Serialize, Debug, Clone)]
@@ -136,8 +418,132 @@ pub struct MessageWithGroupResponseDto {
         pub updated_at: chrono::NaiveDateTime,
         pub group: GroupResponseDto,
         pub source: UserResponseDto,
-        pub content: String,
+        pub content: Option<String>,
         pub idempotency_key: Option<String>,
+        pub edited_at: Option<chrono::NaiveDateTime>,
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+        pub reactions: Vec<MessageReactionResponseDto>,
+        pub reply_to: Option<MessageReplyResponseDto>,
+        pub receipt: Option<ReceiptStatusResponseDto>,
+        pub kind: MessageKind,
+        pub system_event: Option<SystemEvent>,
+        pub expires_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<(MessageWithRelationships, i64)> for MessageWithGroupResponseDto {
+        fn from((message, user_id): (MessageWithRelationships, i64)) -> Self {
+                let receipt = receipt_status(&message.group.users, message.id, message.source.id, user_id);
+
+                MessageWithGroupResponseDto {
+                        id: message.id.to_string(),
+                        created_at: message.created_at,
+                        updated_at: message.updated_at,
+                        group: GroupResponseDto::from((message.group, user_id)),
+                        source: UserResponseDto::from(message.source),
+                        content: message.content.get(&user_id).cloned(),
+                        idempotency_key: message.idempotency_key,
+                        edited_at: message.edited_at,
+                        deleted_at: message.deleted_at,
+                        reactions: aggregate_reactions(&message.reactions, user_id),
+                        reply_to: message
+                                .reply_to
+                                .map(|reply_to| MessageReplyResponseDto::from((reply_to, user_id))),
+                        receipt,
+                        kind: message.kind,
+                        system_event: message.system_event,
+                        expires_at: message.expires_at,
+                }
+        }
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadResponseDto {
+        pub group_id: String,
+        pub unread_count: i32,
+        pub last_read_message_id: Option<String>,
+        pub marked_unread: bool,
+}
+
+impl From<(GroupUser, i64)> for UnreadResponseDto {
+        fn from((group_user, unread_count): (GroupUser, i64)) -> Self {
+                UnreadResponseDto {
+                        group_id: group_user.group_id.to_string(),
+                        unread_count: unread_count as i32,
+                        last_read_message_id: group_user.last_read_message_id.map(|id| id.to_string()),
+                        marked_unread: group_user.marked_unread,
+                }
+        }
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupSettingsResponseDto {
+        pub group_id: String,
+        pub muted_until: Option<chrono::NaiveDateTime>,
+        pub archived: bool,
+        pub pinned: bool,
+}
+
+impl From<GroupUser> for GroupSettingsResponseDto {
+        fn from(group_user: GroupUser) -> Self {
+                GroupSettingsResponseDto {
+                        group_id: group_user.group_id.to_string(),
+                        muted_until: group_user.muted_until,
+                        archived: group_user.archived,
+                        pinned: group_user.pinned,
+                }
+        }
+}
+
+#[derive(Type, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxRequestDto {
+        pub include_archived: Option<bool>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationResponseDto {
+        pub message: MessageWithGroupResponseDto,
+        pub unread: UnreadResponseDto,
+        pub settings: GroupSettingsResponseDto,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MembershipResponseDto {
+        pub group: GroupResponseDto,
+        pub added_user_ids: Vec<String>,
+        pub removed_user_ids: Vec<String>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageExpiredResponseDto {
+        pub group_id: String,
+        pub message_ids: Vec<String>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TypingResponseDto {
+        pub group_id: String,
+        pub user_id: String,
+        pub typing: bool,
+        pub expires_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum MessageEventDto {
+        Message(Box<MessageWithGroupResponseDto>),
+        Receipt(ReceiptResponseDto),
+        Typing(TypingResponseDto),
+        MembershipChanged(MembershipResponseDto),
+        GroupUpdated(GroupResponseDto),
+        MessageExpired(MessageExpiredResponseDto),
+        MessageRequest(MessageRequestResponseDto),
 }
 
 #[derive(Type, Deserialize, Debug, Clone)]
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use authorization::get_cached_token_data;
 use axum::http::request::Parts;
@@ -15,12 +16,14 @@ use diesel::PgConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use dotenvy::dotenv;
 use dtos::{
-        MessageRequestDto, MessageRequestRequestDto, MessageWithGroupResponseDto, PresignedUploadUrlRequestDto,
-        UserPushSubscriptionRequestDto, UserRequestDto,
+        GroupInviteRequestDto, GroupMembersRequestDto, GroupRequestDto, InboxRequestDto, MessageEditRequestDto,
+        MessageEventDto, MessageRequestDto, MessageRequestPageRequestDto, MessageRequestRequestDto, PageRequestDto,
+        PresignedUploadUrlRequestDto, UserPushSubscriptionRequestDto, UserRequestDto,
 };
-use models::User;
+use models::{GroupRole, User};
 use repositories::{
-        group_repository::GroupRepository, message_repository::MessageRepository,
+        group_invite_repository::GroupInviteRepository, group_repository::GroupRepository,
+        message_reaction_repository::MessageReactionRepository, message_repository::MessageRepository,
         message_request_repository::MessageRequestRepository,
         user_push_subscription_repository::UserPushSubscriptionRepository, user_repository::UserRepository,
 };
@@ -55,18 +58,39 @@ struct RequestContext {
 
 struct AppState {
         auth_user_cache: Arc<RwLock<HashMap<String, User>>>,
-        message_senders: Arc<RwLock<HashMap<i64, Sender<MessageWithGroupResponseDto>>>>,
+        message_senders: Arc<RwLock<HashMap<i64, Sender<MessageEventDto>>>>,
+        typing_expirations: Arc<RwLock<HashMap<(i64, i64), Instant>>>,
         id_generator: Arc<Mutex<SnowflakeIdGenerator>>,
 
         google_cloud_storage_service: GoogleCloudStorageService,
 
+        group_invite_repository: GroupInviteRepository,
         group_repository: GroupRepository,
+        message_reaction_repository: MessageReactionRepository,
         message_repository: MessageRepository,
         message_request_repository: MessageRequestRepository,
         user_push_subscription_repository: UserPushSubscriptionRepository,
         user_repository: UserRepository,
 }
 
+impl AppState {
+        pub async fn send_event(&self, user_id: i64, event: MessageEventDto) -> bool {
+                match self.message_senders.read().await.get(&user_id) {
+                        Some(sender) => match sender.send(event) {
+                                Ok(_) => {
+                                        tracing::debug!("Event sent to user {}", user_id);
+                                        true
+                                }
+                                Err(e) => {
+                                        tracing::error!("Failed to send event to user: {:?}", e);
+                                        false
+                                }
+                        },
+                        None => false,
+                }
+        }
+}
+
 impl RequestContext {
         pub async fn get_auth_user(&self) -> Result<User, Error> {
                 let sub = self.sub.as_ref().ok_or_else(|| {
@@ -122,10 +146,98 @@ async fn main() {
                 .query("getGroup", |t| {
                         t(|ctx: RequestContext, group_id: String| group_controller::get_group(ctx, group_id))
                 })
+                .mutation("createGroup", |t| {
+                        t(|ctx: RequestContext, group_request: GroupRequestDto| {
+                                group_controller::create_group(ctx, group_request)
+                        })
+                })
+                .mutation("addGroupMembers", |t| {
+                        t(
+                                |ctx: RequestContext,
+                                 (group_id, group_members_request): (String, GroupMembersRequestDto)| {
+                                        group_controller::add_group_members(ctx, group_id, group_members_request)
+                                },
+                        )
+                })
+                .mutation("removeGroupMember", |t| {
+                        t(|ctx: RequestContext, (group_id, user_id): (String, String)| {
+                                group_controller::remove_group_member(ctx, group_id, user_id)
+                        })
+                })
+                .mutation("leaveGroup", |t| {
+                        t(|ctx: RequestContext, group_id: String| group_controller::leave_group(ctx, group_id))
+                })
+                .mutation("renameGroup", |t| {
+                        t(|ctx: RequestContext, (group_id, name): (String, Option<String>)| {
+                                group_controller::rename_group(ctx, group_id, name)
+                        })
+                })
+                .mutation("setMemberNickname", |t| {
+                        t(
+                                |ctx: RequestContext, (group_id, user_id, nickname): (String, String, Option<String>)| {
+                                        group_controller::set_member_nickname(ctx, group_id, user_id, nickname)
+                                },
+                        )
+                })
+                .mutation("setMemberRole", |t| {
+                        t(
+                                |ctx: RequestContext, (group_id, user_id, role): (String, String, GroupRole)| {
+                                        group_controller::set_member_role(ctx, group_id, user_id, role)
+                                },
+                        )
+                })
+                .mutation("createGroupInvite", |t| {
+                        t(
+                                |ctx: RequestContext, (group_id, group_invite_request): (String, GroupInviteRequestDto)| {
+                                        group_controller::create_group_invite(ctx, group_id, group_invite_request)
+                                },
+                        )
+                })
+                .query("getGroupInvites", |t| {
+                        t(|ctx: RequestContext, group_id: String| group_controller::get_group_invites(ctx, group_id))
+                })
+                .mutation("revokeGroupInvite", |t| {
+                        t(|ctx: RequestContext, (group_id, group_invite_id): (String, String)| {
+                                group_controller::revoke_group_invite(ctx, group_id, group_invite_id)
+                        })
+                })
+                .mutation("redeemGroupInvite", |t| {
+                        t(|ctx: RequestContext, token: String| group_controller::redeem_group_invite(ctx, token))
+                })
+                .mutation("createGroupAvatarPresignedUploadUrl", |t| {
+                        t(
+                                |ctx: RequestContext,
+                                 (group_id, presigned_upload_url_request): (String, PresignedUploadUrlRequestDto)| {
+                                        group_controller::create_group_avatar_presigned_upload_url(
+                                                ctx,
+                                                group_id,
+                                                presigned_upload_url_request,
+                                        )
+                                },
+                        )
+                })
+                .mutation("confirmGroupAvatarUpload", |t| {
+                        t(|ctx: RequestContext, group_id: String| {
+                                group_controller::confirm_group_avatar_upload(ctx, group_id)
+                        })
+                })
+                .mutation("setGroupMessageTtl", |t| {
+                        t(|ctx: RequestContext, (group_id, message_ttl_seconds): (String, Option<i32>)| {
+                                group_controller::set_group_message_ttl(ctx, group_id, message_ttl_seconds)
+                        })
+                })
                 .query("getGroupMessages", |t| {
                         t(
-                                |ctx: RequestContext, group_id: String| {
-                                        group_controller::get_group_messages(ctx, group_id)
+                                |ctx: RequestContext, (group_id, page_request): (String, PageRequestDto)| {
+                                        group_controller::get_group_messages(ctx, group_id, page_request)
+                                },
+                        )
+                })
+                .query("getThread", |t| {
+                        t(
+                                |ctx: RequestContext,
+                                 (group_id, message_id, page_request): (String, String, PageRequestDto)| {
+                                        group_controller::get_thread(ctx, group_id, message_id, page_request)
                                 },
                         )
                 })
@@ -135,11 +247,87 @@ async fn main() {
                                         group_controller::create_group_message(ctx, group_id, message_request)
                                 },
                         )
+                })
+                .mutation("editGroupMessage", |t| {
+                        t(
+                                |ctx: RequestContext,
+                                 (group_id, message_id, message_edit_request): (
+                                        String,
+                                        String,
+                                        MessageEditRequestDto,
+                                )| {
+                                        group_controller::edit_group_message(
+                                                ctx,
+                                                group_id,
+                                                message_id,
+                                                message_edit_request,
+                                        )
+                                },
+                        )
+                })
+                .mutation("deleteGroupMessage", |t| {
+                        t(|ctx: RequestContext, (group_id, message_id): (String, String)| {
+                                group_controller::delete_group_message(ctx, group_id, message_id)
+                        })
+                })
+                .mutation("markGroupRead", |t| {
+                        t(|ctx: RequestContext, (group_id, message_id): (String, String)| {
+                                group_controller::mark_group_read(ctx, group_id, message_id)
+                        })
+                })
+                .mutation("markGroupUnread", |t| {
+                        t(
+                                |ctx: RequestContext, group_id: String| {
+                                        group_controller::mark_group_unread(ctx, group_id)
+                                },
+                        )
+                })
+                .mutation("setGroupMuted", |t| {
+                        t(
+                                |ctx: RequestContext,
+                                 (group_id, muted_until): (String, Option<chrono::NaiveDateTime>)| {
+                                        group_controller::set_group_muted(ctx, group_id, muted_until)
+                                },
+                        )
+                })
+                .mutation("setGroupArchived", |t| {
+                        t(|ctx: RequestContext, (group_id, archived): (String, bool)| {
+                                group_controller::set_group_archived(ctx, group_id, archived)
+                        })
+                })
+                .mutation("setGroupPinned", |t| {
+                        t(|ctx: RequestContext, (group_id, pinned): (String, bool)| {
+                                group_controller::set_group_pinned(ctx, group_id, pinned)
+                        })
+                })
+                .mutation("startTyping", |t| {
+                        t(|ctx: RequestContext, group_id: String| group_controller::start_typing(ctx, group_id))
+                })
+                .mutation("stopTyping", |t| {
+                        t(|ctx: RequestContext, group_id: String| group_controller::stop_typing(ctx, group_id))
+                })
+                .mutation("addGroupMessageReaction", |t| {
+                        t(
+                                |ctx: RequestContext, (group_id, message_id, emoji): (String, String, String)| {
+                                        group_controller::add_group_message_reaction(ctx, group_id, message_id, emoji)
+                                },
+                        )
+                })
+                .mutation("removeGroupMessageReaction", |t| {
+                        t(
+                                |ctx: RequestContext, (group_id, message_id, emoji): (String, String, String)| {
+                                        group_controller::remove_group_message_reaction(
+                                                ctx, group_id, message_id, emoji,
+                                        )
+                                },
+                        )
                 });
 
         let message_router = rspc::Router::<RequestContext>::new()
                 .query("getMessages", |t| {
-                        t(|ctx: RequestContext, _: ()| message_controller::get_messages(ctx))
+                        t(|ctx: RequestContext, inbox_request: Option<InboxRequestDto>| {
+                                message_controller::get_messages(ctx, inbox_request)
+                        })
                 })
                 .subscription("subscribeToMessages", |t| {
                         t(|ctx, _: ()| {
@@ -175,6 +363,16 @@ async fn main() {
                                 message_request_controller::get_message_request(ctx, message_request_id)
                         })
                 })
+                .query("listIncomingMessageRequests", |t| {
+                        t(|ctx: RequestContext, page_request: MessageRequestPageRequestDto| {
+                                message_request_controller::list_incoming_message_requests(ctx, page_request)
+                        })
+                })
+                .query("listOutgoingMessageRequests", |t| {
+                        t(|ctx: RequestContext, page_request: MessageRequestPageRequestDto| {
+                                message_request_controller::list_outgoing_message_requests(ctx, page_request)
+                        })
+                })
                 .mutation("createMessageRequest", |t| {
                         t(
                                 |ctx: RequestContext, message_request_request: MessageRequestRequestDto| {
@@ -186,6 +384,21 @@ async fn main() {
                         t(|ctx: RequestContext, message_request_id: String| {
                                 message_request_controller::approve_message_request(ctx, message_request_id)
                         })
+                })
+                .mutation("declineMessageRequest", |t| {
+                        t(|ctx: RequestContext, message_request_id: String| {
+                                message_request_controller::decline_message_request(ctx, message_request_id)
+                        })
+                })
+                .mutation("ignoreMessageRequest", |t| {
+                        t(|ctx: RequestContext, message_request_id: String| {
+                                message_request_controller::ignore_message_request(ctx, message_request_id)
+                        })
+                })
+                .mutation("cancelMessageRequest", |t| {
+                        t(|ctx: RequestContext, message_request_id: String| {
+                                message_request_controller::cancel_message_request(ctx, message_request_id)
+                        })
                 });
 
         let users_router = rspc::Router::<RequestContext>::new()
@@ -264,6 +477,7 @@ async fn main() {
         let app_state = Arc::new(AppState {
                 auth_user_cache: Arc::new(RwLock::new(HashMap::new())),
                 message_senders: Arc::new(RwLock::new(HashMap::new())),
+                typing_expirations: Arc::new(RwLock::new(HashMap::new())),
 
                 id_generator: Arc::new(Mutex::new(SnowflakeIdGenerator::new(1, 1))),
 
@@ -271,13 +485,17 @@ async fn main() {
                         google_cloud_storage::client::Client::new(gcp_config),
                 ),
 
+                group_invite_repository: GroupInviteRepository::new(pool.clone()),
                 group_repository: GroupRepository::new(pool.clone()),
+                message_reaction_repository: MessageReactionRepository::new(pool.clone()),
                 message_repository: MessageRepository::new(pool.clone()),
                 message_request_repository: MessageRequestRepository::new(pool.clone()),
                 user_push_subscription_repository: UserPushSubscriptionRepository::new(pool.clone()),
                 user_repository: UserRepository::new(pool.clone()),
         });
 
+        tokio::spawn(group_controller::reap_expired_messages(Arc::clone(&app_state)));
+
         let app = axum::Router::new()
                 .route("/health", get(|| async { Json(json!({ "status": "up" })) }))
                 .nest(
@@ -1,6 +1,14 @@
 use crate::schema;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
 use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Varchar;
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use std::collections::HashMap;
+use std::io::Write;
 
 #[derive(Queryable, Identifiable, Selectable, Insertable, AsChangeset, Debug, Clone)]
 #[diesel(table_name = schema::users)]
@@ -27,8 +35,55 @@ pub struct MessageRequest {
         pub source_id: i64,
         pub destination_id: i64,
         pub approved_at: Option<chrono::NaiveDateTime>,
+        pub status: MessageRequestStatus,
+        pub group_id: Option<i64>,
 }
 
+/// Declined requests put the sender on a cool-down; ignored requests are dismissed without one.
+#[derive(AsExpression, FromSqlRow, Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = Varchar)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageRequestStatus {
+        Pending,
+        Approved,
+        Declined,
+        Ignored,
+        Cancelled,
+}
+
+impl MessageRequestStatus {
+        pub fn as_str(&self) -> &'static str {
+                match self {
+                        MessageRequestStatus::Pending => "pending",
+                        MessageRequestStatus::Approved => "approved",
+                        MessageRequestStatus::Declined => "declined",
+                        MessageRequestStatus::Ignored => "ignored",
+                        MessageRequestStatus::Cancelled => "cancelled",
+                }
+        }
+}
+
+impl ToSql<Varchar, Pg> for MessageRequestStatus {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+                out.write_all(self.as_str().as_bytes())?;
+                Ok(IsNull::No)
+        }
+}
+
+impl FromSql<Varchar, Pg> for MessageRequestStatus {
+        fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+                match bytes.as_bytes() {
+                        b"pending" => Ok(MessageRequestStatus::Pending),
+                        b"approved" => Ok(MessageRequestStatus::Approved),
+                        b"declined" => Ok(MessageRequestStatus::Declined),
+                        b"ignored" => Ok(MessageRequestStatus::Ignored),
+                        b"cancelled" => Ok(MessageRequestStatus::Cancelled),
+                        _ => Err("Unrecognized message request status".into()),
+                }
+        }
+}
+
+#[derive(Debug, Clone)]
 pub struct MessageRequestWithRelationships {
         pub id: i64,
         pub created_at: chrono::NaiveDateTime,
@@ -36,6 +91,8 @@ pub struct MessageRequestWithRelationships {
         pub source: User,
         pub destination: User,
         pub approved_at: Option<chrono::NaiveDateTime>,
+        pub status: MessageRequestStatus,
+        pub group_id: Option<i64>,
 }
 
 impl From<(MessageRequest, User, User)> for MessageRequestWithRelationships {
@@ -47,12 +104,15 @@ impl From<(MessageRequest, User, User)> for MessageRequestWithRelationships {
                         source,
                         destination,
                         approved_at: message_request.approved_at,
+                        status: message_request.status,
+                        group_id: message_request.group_id,
                 }
         }
 }
 
 #[derive(Queryable, Identifiable, Selectable, Insertable, AsChangeset, Debug, Clone)]
 #[diesel(table_name = schema::groups)]
+#[diesel(treat_none_as_null = true)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Group {
         pub id: i64,
@@ -60,6 +120,160 @@ pub struct Group {
         pub updated_at: chrono::NaiveDateTime,
         pub name: Option<String>,
         pub message_request_id: Option<i64>,
+        pub archived_at: Option<chrono::NaiveDateTime>,
+        pub avatar_url: Option<String>,
+        pub message_ttl_seconds: Option<i32>,
+}
+
+/// Ordered from least to most privileged so roles can be compared by rank.
+#[derive(
+        AsExpression, FromSqlRow, Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[diesel(sql_type = Varchar)]
+#[serde(rename_all = "camelCase")]
+pub enum GroupRole {
+        Member,
+        Moderator,
+        Admin,
+        Owner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupPermission {
+        Post,
+        Invite,
+        Remove,
+        Rename,
+        UpdateAvatar,
+        UpdateMessageTtl,
+        DeleteOthersMessages,
+        ManageRoles,
+}
+
+impl GroupRole {
+        pub fn as_str(&self) -> &'static str {
+                match self {
+                        GroupRole::Member => "member",
+                        GroupRole::Moderator => "moderator",
+                        GroupRole::Admin => "admin",
+                        GroupRole::Owner => "owner",
+                }
+        }
+
+        pub fn has_permission(&self, permission: GroupPermission) -> bool {
+                match permission {
+                        GroupPermission::Post => true,
+                        GroupPermission::Remove | GroupPermission::DeleteOthersMessages => {
+                                *self >= GroupRole::Moderator
+                        }
+                        GroupPermission::Invite
+                        | GroupPermission::Rename
+                        | GroupPermission::UpdateAvatar
+                        | GroupPermission::UpdateMessageTtl
+                        | GroupPermission::ManageRoles => *self >= GroupRole::Admin,
+                }
+        }
+}
+
+impl ToSql<Varchar, Pg> for GroupRole {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+                out.write_all(self.as_str().as_bytes())?;
+                Ok(IsNull::No)
+        }
+}
+
+impl FromSql<Varchar, Pg> for GroupRole {
+        fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+                match bytes.as_bytes() {
+                        b"member" => Ok(GroupRole::Member),
+                        b"moderator" => Ok(GroupRole::Moderator),
+                        b"admin" => Ok(GroupRole::Admin),
+                        b"owner" => Ok(GroupRole::Owner),
+                        _ => Err("Unrecognized group role".into()),
+                }
+        }
+}
+
+#[derive(AsExpression, FromSqlRow, Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = Varchar)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageKind {
+        User,
+        System,
+}
+
+impl MessageKind {
+        pub fn as_str(&self) -> &'static str {
+                match self {
+                        MessageKind::User => "user",
+                        MessageKind::System => "system",
+                }
+        }
+}
+
+impl ToSql<Varchar, Pg> for MessageKind {
+        fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+                out.write_all(self.as_str().as_bytes())?;
+                Ok(IsNull::No)
+        }
+}
+
+impl FromSql<Varchar, Pg> for MessageKind {
+        fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+                match bytes.as_bytes() {
+                        b"user" => Ok(MessageKind::User),
+                        b"system" => Ok(MessageKind::System),
+                        _ => Err("Unrecognized message kind".into()),
+                }
+        }
+}
+
+/// Plaintext payload of a server-authored system message, stored in `messages.metadata`.
+#[derive(Type, Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum SystemEvent {
+        MessageRequestApproved {
+                user_ids: Vec<String>,
+        },
+        MembersAdded {
+                actor_id: String,
+                user_ids: Vec<String>,
+        },
+        MemberRemoved {
+                actor_id: String,
+                user_id: String,
+        },
+        MemberJoined {
+                user_id: String,
+        },
+        MemberLeft {
+                user_id: String,
+        },
+        GroupRenamed {
+                actor_id: String,
+                name: Option<String>,
+        },
+        MessageTtlChanged {
+                actor_id: String,
+                message_ttl_seconds: Option<i32>,
+        },
+}
+
+#[derive(Queryable, Identifiable, Selectable, Insertable, Associations, AsChangeset, Debug, Clone)]
+#[diesel(belongs_to(Group))]
+#[diesel(table_name = schema::group_invites)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct GroupInvite {
+        pub id: i64,
+        pub created_at: chrono::NaiveDateTime,
+        pub updated_at: chrono::NaiveDateTime,
+        pub group_id: i64,
+        pub source_id: i64,
+        pub token: String,
+        pub expires_at: chrono::NaiveDateTime,
+        pub max_uses: Option<i32>,
+        pub use_count: i32,
+        pub revoked_at: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Queryable, Identifiable, Selectable, Insertable, Associations, AsChangeset, Debug, Clone)]
@@ -72,8 +286,24 @@ pub struct GroupUser {
         pub updated_at: chrono::NaiveDateTime,
         pub group_id: i64,
         pub user_id: i64,
-        pub is_admin: bool,
         pub nickname: Option<String>,
+        pub last_delivered_message_id: Option<i64>,
+        pub last_read_message_id: Option<i64>,
+        pub marked_unread: bool,
+        pub role: GroupRole,
+        pub muted_until: Option<chrono::NaiveDateTime>,
+        pub archived: bool,
+        pub pinned: bool,
+}
+
+#[derive(AsChangeset, Debug, Clone)]
+#[diesel(table_name = schema::group_users)]
+#[diesel(treat_none_as_null = true)]
+pub struct GroupUserSettings {
+        pub updated_at: chrono::NaiveDateTime,
+        pub muted_until: Option<chrono::NaiveDateTime>,
+        pub archived: bool,
+        pub pinned: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -83,6 +313,9 @@ pub struct GroupWithRelationships {
         pub updated_at: chrono::NaiveDateTime,
         pub name: Option<String>,
         pub message_request_id: Option<i64>,
+        pub archived_at: Option<chrono::NaiveDateTime>,
+        pub avatar_url: Option<String>,
+        pub message_ttl_seconds: Option<i32>,
         pub users: Vec<GroupUserWithRelationships>,
 }
 
@@ -92,8 +325,14 @@ pub struct GroupUserWithRelationships {
         pub created_at: chrono::NaiveDateTime,
         pub updated_at: chrono::NaiveDateTime,
         pub user: User,
-        pub is_admin: bool,
         pub nickname: Option<String>,
+        pub last_delivered_message_id: Option<i64>,
+        pub last_read_message_id: Option<i64>,
+        pub marked_unread: bool,
+        pub role: GroupRole,
+        pub muted_until: Option<chrono::NaiveDateTime>,
+        pub archived: bool,
+        pub pinned: bool,
 }
 
 impl From<(Group, Vec<GroupUserWithRelationships>)> for GroupWithRelationships {
@@ -104,6 +343,9 @@ impl From<(Group, Vec<GroupUserWithRelationships>)> for GroupWithRelationships {
                         updated_at: group.updated_at,
                         name: group.name,
                         message_request_id: group.message_request_id,
+                        archived_at: group.archived_at,
+                        avatar_url: group.avatar_url,
+                        message_ttl_seconds: group.message_ttl_seconds,
                         users,
                 }
         }
@@ -116,8 +358,34 @@ impl From<(GroupUser, User)> for GroupUserWithRelationships {
                         created_at: group_user.created_at,
                         updated_at: group_user.updated_at,
                         user,
-                        is_admin: group_user.is_admin,
                         nickname: group_user.nickname,
+                        last_delivered_message_id: group_user.last_delivered_message_id,
+                        last_read_message_id: group_user.last_read_message_id,
+                        marked_unread: group_user.marked_unread,
+                        role: group_user.role,
+                        muted_until: group_user.muted_until,
+                        archived: group_user.archived,
+                        pinned: group_user.pinned,
+                }
+        }
+}
+
+impl From<(i64, GroupUserWithRelationships)> for GroupUser {
+        fn from((group_id, gu): (i64, GroupUserWithRelationships)) -> Self {
+                GroupUser {
+                        id: gu.id,
+                        created_at: gu.created_at,
+                        updated_at: gu.updated_at,
+                        group_id,
+                        user_id: gu.user.id,
+                        nickname: gu.nickname,
+                        last_delivered_message_id: gu.last_delivered_message_id,
+                        last_read_message_id: gu.last_read_message_id,
+                        marked_unread: gu.marked_unread,
+                        role: gu.role,
+                        muted_until: gu.muted_until,
+                        archived: gu.archived,
+                        pinned: gu.pinned,
                 }
         }
 }
@@ -133,6 +401,12 @@ pub struct Message {
         pub group_id: i64,
         pub source_id: i64,
         pub idempotency_key: Option<String>,
+        pub edited_at: Option<chrono::NaiveDateTime>,
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+        pub reply_to_id: Option<i64>,
+        pub kind: MessageKind,
+        pub metadata: Option<serde_json::Value>,
+        pub expires_at: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Queryable, Identifiable, Selectable, Insertable, Associations, AsChangeset, Debug, Clone)]
@@ -146,6 +420,61 @@ pub struct MessageContent {
         pub content: String,
 }
 
+#[derive(Queryable, Selectable, Insertable, Associations, Debug, Clone)]
+#[diesel(belongs_to(Message))]
+#[diesel(table_name = schema::message_content_revisions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MessageContentRevision {
+        pub message_id: i64,
+        pub user_id: i64,
+        pub created_at: chrono::NaiveDateTime,
+        pub content: String,
+}
+
+#[derive(Queryable, Selectable, Insertable, Associations, Debug, Clone)]
+#[diesel(belongs_to(Message))]
+#[diesel(table_name = schema::message_reactions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MessageReaction {
+        pub message_id: i64,
+        pub user_id: i64,
+        pub emoji: String,
+        pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageReply {
+        pub id: i64,
+        pub created_at: chrono::NaiveDateTime,
+        pub source: User,
+        pub content: HashMap<i64, String>,
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<(Message, User, HashMap<i64, String>)> for MessageReply {
+        fn from((message, source, content): (Message, User, HashMap<i64, String>)) -> Self {
+                MessageReply {
+                        id: message.id,
+                        created_at: message.created_at,
+                        source,
+                        content,
+                        deleted_at: message.deleted_at,
+                }
+        }
+}
+
+impl From<MessageWithRelationships> for MessageReply {
+        fn from(message: MessageWithRelationships) -> Self {
+                MessageReply {
+                        id: message.id,
+                        created_at: message.created_at,
+                        source: message.source,
+                        content: message.content,
+                        deleted_at: message.deleted_at,
+                }
+        }
+}
+
 #[derive(Debug, Clone)]
 pub struct MessageWithSource {
         pub id: i64,
@@ -153,20 +482,52 @@ pub struct MessageWithSource {
         pub updated_at: chrono::NaiveDateTime,
         pub group_id: i64,
         pub source: User,
-        pub content: HashMap<i64, String>,
+        pub content: Option<String>,
         pub idempotency_key: Option<String>,
+        pub edited_at: Option<chrono::NaiveDateTime>,
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+        pub reactions: Vec<MessageReaction>,
+        pub reply_to: Option<MessageReply>,
+        pub kind: MessageKind,
+        pub system_event: Option<SystemEvent>,
+        pub expires_at: Option<chrono::NaiveDateTime>,
 }
 
-impl From<(Message, User, HashMap<i64, String>)> for MessageWithSource {
-        fn from((message, source, content): (Message, User, HashMap<i64, String>)) -> Self {
+impl
+        From<(
+                Message,
+                User,
+                Option<MessageContent>,
+                Vec<MessageReaction>,
+                Option<MessageReply>,
+        )> for MessageWithSource
+{
+        fn from(
+                (message, source, content, reactions, reply_to): (
+                        Message,
+                        User,
+                        Option<MessageContent>,
+                        Vec<MessageReaction>,
+                        Option<MessageReply>,
+                ),
+        ) -> Self {
                 MessageWithSource {
                         id: message.id,
                         created_at: message.created_at,
                         updated_at: message.updated_at,
                         group_id: message.group_id,
                         source,
-                        content,
+                        content: content.map(|content| content.content),
                         idempotency_key: message.idempotency_key,
+                        edited_at: message.edited_at,
+                        deleted_at: message.deleted_at,
+                        reactions,
+                        reply_to,
+                        kind: message.kind,
+                        system_event: message
+                                .metadata
+                                .and_then(|metadata| serde_json::from_value(metadata).ok()),
+                        expires_at: message.expires_at,
                 }
         }
 }
@@ -180,10 +541,35 @@ pub struct MessageWithGroup {
         pub source: User,
         pub content: String,
         pub idempotency_key: Option<String>,
+        pub edited_at: Option<chrono::NaiveDateTime>,
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+        pub reactions: Vec<MessageReaction>,
+        pub reply_to: Option<MessageReply>,
+        pub kind: MessageKind,
+        pub system_event: Option<SystemEvent>,
+        pub expires_at: Option<chrono::NaiveDateTime>,
 }
 
-impl From<(Message, GroupWithRelationships, User, MessageContent)> for MessageWithGroup {
-        fn from((message, group, source, content): (Message, GroupWithRelationships, User, MessageContent)) -> Self {
+impl
+        From<(
+                Message,
+                GroupWithRelationships,
+                User,
+                MessageContent,
+                Vec<MessageReaction>,
+                Option<MessageReply>,
+        )> for MessageWithGroup
+{
+        fn from(
+                (message, group, source, content, reactions, reply_to): (
+                        Message,
+                        GroupWithRelationships,
+                        User,
+                        MessageContent,
+                        Vec<MessageReaction>,
+                        Option<MessageReply>,
+                ),
+        ) -> Self {
                 MessageWithGroup {
                         id: message.id,
                         created_at: message.created_at,
@@ -192,6 +578,15 @@ impl From<(Message, GroupWithRelationships, User, MessageContent)> for MessageWi
                         source,
                         content: content.content,
                         idempotency_key: message.idempotency_key,
+                        edited_at: message.edited_at,
+                        deleted_at: message.deleted_at,
+                        reactions,
+                        reply_to,
+                        kind: message.kind,
+                        system_event: message
+                                .metadata
+                                .and_then(|metadata| serde_json::from_value(metadata).ok()),
+                        expires_at: message.expires_at,
                 }
         }
 }
@@ -205,11 +600,34 @@ pub struct MessageWithRelationships {
         pub source: User,
         pub content: HashMap<i64, String>,
         pub idempotency_key: Option<String>,
+        pub edited_at: Option<chrono::NaiveDateTime>,
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+        pub reactions: Vec<MessageReaction>,
+        pub reply_to: Option<MessageReply>,
+        pub kind: MessageKind,
+        pub system_event: Option<SystemEvent>,
+        pub expires_at: Option<chrono::NaiveDateTime>,
 }
 
-impl From<(Message, GroupWithRelationships, User, HashMap<i64, String>)> for MessageWithRelationships {
+impl
+        From<(
+                Message,
+                GroupWithRelationships,
+                User,
+                HashMap<i64, String>,
+                Vec<MessageReaction>,
+                Option<MessageReply>,
+        )> for MessageWithRelationships
+{
         fn from(
-                (message, group, source, content): (Message, GroupWithRelationships, User, HashMap<i64, String>),
+                (message, group, source, content, reactions, reply_to): (
+                        Message,
+                        GroupWithRelationships,
+                        User,
+                        HashMap<i64, String>,
+                        Vec<MessageReaction>,
+                        Option<MessageReply>,
+                ),
         ) -> Self {
                 MessageWithRelationships {
                         id: message.id,
@@ -219,6 +637,37 @@ impl From<(Message, GroupWithRelationships, User, HashMap<i64, String>)> for Mes
                         source,
                         content,
                         idempotency_key: message.idempotency_key,
+                        edited_at: message.edited_at,
+                        deleted_at: message.deleted_at,
+                        reactions,
+                        reply_to,
+                        kind: message.kind,
+                        system_event: message
+                                .metadata
+                                .and_then(|metadata| serde_json::from_value(metadata).ok()),
+                        expires_at: message.expires_at,
+                }
+        }
+}
+
+impl From<&MessageWithRelationships> for Message {
+        fn from(message_with_relationships: &MessageWithRelationships) -> Self {
+                Message {
+                        id: message_with_relationships.id,
+                        created_at: message_with_relationships.created_at,
+                        updated_at: message_with_relationships.updated_at,
+                        group_id: message_with_relationships.group.id,
+                        source_id: message_with_relationships.source.id,
+                        idempotency_key: message_with_relationships.idempotency_key.clone(),
+                        edited_at: message_with_relationships.edited_at,
+                        deleted_at: message_with_relationships.deleted_at,
+                        reply_to_id: message_with_relationships.reply_to.as_ref().map(|reply_to| reply_to.id),
+                        kind: message_with_relationships.kind,
+                        metadata: message_with_relationships
+                                .system_event
+                                .as_ref()
+                                .and_then(|system_event| serde_json::to_value(system_event).ok()),
+                        expires_at: message_with_relationships.expires_at,
                 }
         }
 }
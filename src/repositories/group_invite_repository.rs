@@ -0,0 +1,93 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
+use rspc::{Error, ErrorCode};
+
+use crate::{models::GroupInvite, schema::group_invites};
+
+#[derive(Debug, Clone)]
+pub struct GroupInviteRepository {
+        pool: r2d2::Pool<ConnectionManager<PgConnection>>,
+}
+
+impl GroupInviteRepository {
+        pub fn new(pool: r2d2::Pool<ConnectionManager<PgConnection>>) -> Self {
+                Self { pool }
+        }
+
+        pub fn find_active_by_group_id(&self, group_id: i64) -> Result<Vec<GroupInvite>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                group_invites::table
+                        .filter(group_invites::group_id.eq(group_id))
+                        .filter(group_invites::revoked_at.is_null())
+                        .filter(group_invites::expires_at.gt(Utc::now().naive_utc()))
+                        .filter(group_invites::max_uses
+                                .is_null()
+                                .or(group_invites::use_count.lt(group_invites::max_uses.assume_not_null())))
+                        .order_by(group_invites::created_at.desc())
+                        .load::<GroupInvite>(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn find_by_id_and_group_id(&self, invite_id: i64, group_id: i64) -> Result<Option<GroupInvite>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                group_invites::table
+                        .filter(group_invites::id
+                                .eq(invite_id)
+                                .and(group_invites::group_id.eq(group_id)))
+                        .first::<GroupInvite>(&mut connection)
+                        .optional()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn find_by_token(&self, token: &str) -> Result<Option<GroupInvite>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                group_invites::table
+                        .filter(group_invites::token.eq(token))
+                        .first::<GroupInvite>(&mut connection)
+                        .optional()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn update_revoked_at(&self, invite_id: i64) -> Result<GroupInvite, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(group_invites::table.find(invite_id))
+                        .set((
+                                group_invites::updated_at.eq(Utc::now().naive_utc()),
+                                group_invites::revoked_at.eq(Utc::now().naive_utc()),
+                        ))
+                        .get_result::<GroupInvite>(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn save(&self, group_invite: GroupInvite) -> Result<GroupInvite, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::insert_into(group_invites::table)
+                        .values(&group_invite)
+                        .on_conflict(group_invites::id)
+                        .do_update()
+                        .set(&group_invite)
+                        .get_result::<GroupInvite>(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+}
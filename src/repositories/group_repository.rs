@@ -1,12 +1,19 @@
+use chrono::Utc;
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
+use diesel::sql_types::{BigInt, Nullable};
 use rspc::{Error, ErrorCode};
 
 use crate::{
-        models::{Group, GroupUser, GroupUserWithRelationships, GroupWithRelationships, User},
-        schema::{group_users, groups, users},
+        models::{
+                Group, GroupRole, GroupUser, GroupUserSettings, GroupUserWithRelationships, GroupWithRelationships,
+                User,
+        },
+        schema::{group_invites, group_users, groups, users},
 };
 
+define_sql_function!(fn greatest(a: Nullable<BigInt>, b: BigInt) -> Nullable<BigInt>);
+
 #[derive(Debug, Clone)]
 pub struct GroupRepository {
         pool: r2d2::Pool<ConnectionManager<PgConnection>>,
@@ -17,11 +24,7 @@ impl GroupRepository {
                 Self { pool }
         }
 
-        pub fn find_by_id_and_user_id(
-                &self,
-                group_id: i64,
-                user_id: i64,
-        ) -> Result<Option<GroupWithRelationships>, Error> {
+        pub fn find_by_id(&self, group_id: i64) -> Result<Option<GroupWithRelationships>, Error> {
                 let mut connection = self
                         .pool
                         .get()
@@ -42,10 +45,6 @@ impl GroupRepository {
                                         Error::new(ErrorCode::InternalServerError, "Failed to query database".into())
                                 })?;
 
-                        if !group_users.iter().any(|gu: &GroupUser| gu.user_id == user_id) {
-                                return Ok(None);
-                        }
-
                         let group_users_with_relationships: Vec<GroupUserWithRelationships> = group_users
                                 .into_iter()
                                 .map(|gu| {
@@ -74,6 +73,353 @@ impl GroupRepository {
                 })
         }
 
+        pub fn find_by_id_and_user_id(
+                &self,
+                group_id: i64,
+                user_id: i64,
+        ) -> Result<Option<GroupWithRelationships>, Error> {
+                let group = self.find_by_id(group_id)?;
+
+                Ok(group.filter(|group| group.users.iter().any(|gu| gu.user.id == user_id)))
+        }
+
+        pub fn update_last_delivered_message_id(
+                &self,
+                group_id: i64,
+                user_id: i64,
+                message_id: i64,
+        ) -> Result<GroupUser, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(
+                        group_users::table
+                                .filter(group_users::group_id.eq(group_id).and(group_users::user_id.eq(user_id))),
+                )
+                .set((
+                        group_users::updated_at.eq(Utc::now().naive_utc()),
+                        group_users::last_delivered_message_id
+                                .eq(greatest(group_users::last_delivered_message_id, message_id)),
+                ))
+                .get_result::<GroupUser>(&mut connection)
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn update_last_read_message_id(
+                &self,
+                group_id: i64,
+                user_id: i64,
+                message_id: i64,
+        ) -> Result<GroupUser, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(
+                        group_users::table
+                                .filter(group_users::group_id.eq(group_id).and(group_users::user_id.eq(user_id))),
+                )
+                .set((
+                        group_users::updated_at.eq(Utc::now().naive_utc()),
+                        group_users::last_read_message_id.eq(greatest(group_users::last_read_message_id, message_id)),
+                        group_users::last_delivered_message_id
+                                .eq(greatest(group_users::last_delivered_message_id, message_id)),
+                        group_users::marked_unread.eq(false),
+                ))
+                .get_result::<GroupUser>(&mut connection)
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn update_marked_unread(
+                &self,
+                group_id: i64,
+                user_id: i64,
+                marked_unread: bool,
+        ) -> Result<GroupUser, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(
+                        group_users::table
+                                .filter(group_users::group_id.eq(group_id).and(group_users::user_id.eq(user_id))),
+                )
+                .set((
+                        group_users::updated_at.eq(Utc::now().naive_utc()),
+                        group_users::marked_unread.eq(marked_unread),
+                ))
+                .get_result::<GroupUser>(&mut connection)
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn update_settings(
+                &self,
+                group_id: i64,
+                user_id: i64,
+                group_user_settings: GroupUserSettings,
+        ) -> Result<GroupUser, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(
+                        group_users::table
+                                .filter(group_users::group_id.eq(group_id).and(group_users::user_id.eq(user_id))),
+                )
+                .set(&group_user_settings)
+                .get_result::<GroupUser>(&mut connection)
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn delete_group_user(&self, group_id: i64, user_id: i64) -> Result<(), Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::delete(
+                        group_users::table
+                                .filter(group_users::group_id.eq(group_id).and(group_users::user_id.eq(user_id))),
+                )
+                .execute(&mut connection)
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                Ok(())
+        }
+
+        pub fn delete_group_user_with_handoff(&self, group_id: i64, user_id: i64) -> Result<Option<GroupUser>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                connection
+                        .transaction::<Option<GroupUser>, diesel::result::Error, _>(|connection| {
+                                groups::table.find(group_id).for_update().first::<Group>(connection)?;
+
+                                diesel::delete(group_users::table.filter(
+                                        group_users::group_id.eq(group_id).and(group_users::user_id.eq(user_id)),
+                                ))
+                                .execute(connection)?;
+
+                                let remaining_group_users = group_users::table
+                                        .filter(group_users::group_id.eq(group_id))
+                                        .order_by((group_users::created_at.asc(), group_users::id.asc()))
+                                        .load::<GroupUser>(connection)?;
+
+                                if remaining_group_users.is_empty() {
+                                        diesel::update(groups::table.find(group_id))
+                                                .set((
+                                                        groups::updated_at.eq(Utc::now().naive_utc()),
+                                                        groups::archived_at.eq(Utc::now().naive_utc()),
+                                                ))
+                                                .execute(connection)?;
+
+                                        return Ok(None);
+                                }
+
+                                if remaining_group_users.iter().any(|gu| gu.role == GroupRole::Owner) {
+                                        return Ok(None);
+                                }
+
+                                // Highest role wins, ties go to the longest-standing member
+                                let Some(successor) = remaining_group_users.iter().rev().max_by_key(|gu| gu.role)
+                                else {
+                                        return Ok(None);
+                                };
+
+                                diesel::update(group_users::table.find(successor.id))
+                                        .set((
+                                                group_users::updated_at.eq(Utc::now().naive_utc()),
+                                                group_users::role.eq(GroupRole::Owner),
+                                        ))
+                                        .get_result::<GroupUser>(connection)
+                                        .map(Some)
+                        })
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn update_name(&self, group_id: i64, name: Option<String>) -> Result<Group, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(groups::table.find(group_id))
+                        .set((groups::updated_at.eq(Utc::now().naive_utc()), groups::name.eq(name)))
+                        .get_result::<Group>(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn update_avatar_url(&self, group_id: i64, avatar_url: String) -> Result<Group, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(groups::table.find(group_id))
+                        .set((
+                                groups::updated_at.eq(Utc::now().naive_utc()),
+                                groups::avatar_url.eq(avatar_url),
+                        ))
+                        .get_result::<Group>(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        pub fn update_message_ttl_seconds(
+                &self,
+                group_id: i64,
+                message_ttl_seconds: Option<i32>,
+        ) -> Result<Group, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(groups::table.find(group_id))
+                        .set((
+                                groups::updated_at.eq(Utc::now().naive_utc()),
+                                groups::message_ttl_seconds.eq(message_ttl_seconds),
+                        ))
+                        .get_result::<Group>(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        /// Returns None if the user is no longer a member of the group.
+        pub fn update_nickname(
+                &self,
+                group_id: i64,
+                user_id: i64,
+                nickname: Option<String>,
+        ) -> Result<Option<GroupUser>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(
+                        group_users::table
+                                .filter(group_users::group_id.eq(group_id).and(group_users::user_id.eq(user_id))),
+                )
+                .set((
+                        group_users::updated_at.eq(Utc::now().naive_utc()),
+                        group_users::nickname.eq(nickname),
+                ))
+                .get_result::<GroupUser>(&mut connection)
+                .optional()
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        /// Returns None if the user is no longer a member of the group.
+        pub fn update_role(&self, group_id: i64, user_id: i64, role: GroupRole) -> Result<Option<GroupUser>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::update(
+                        group_users::table
+                                .filter(group_users::group_id.eq(group_id).and(group_users::user_id.eq(user_id))),
+                )
+                .set((
+                        group_users::updated_at.eq(Utc::now().naive_utc()),
+                        group_users::role.eq(role),
+                ))
+                .get_result::<GroupUser>(&mut connection)
+                .optional()
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        /// Adds members to an existing group, skipping any user who is already a member.
+        pub fn save_group_users(
+                &self,
+                group_id: i64,
+                group_users_with_relationships: Vec<GroupUserWithRelationships>,
+        ) -> Result<(), Error> {
+                let group_users = group_users_with_relationships
+                        .into_iter()
+                        .map(|gu| GroupUser::from((group_id, gu)))
+                        .collect::<Vec<GroupUser>>();
+
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::insert_into(group_users::table)
+                        .values(&group_users)
+                        .on_conflict((group_users::group_id, group_users::user_id))
+                        .do_nothing()
+                        .execute(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                Ok(())
+        }
+
+        /// Adds the member and consumes one use of the invite in a single transaction. Returns None when the
+        /// invite is no longer valid, and Some(false) without consuming a use when the user is already a member.
+        pub fn save_group_user_with_invite(
+                &self,
+                group_id: i64,
+                group_user_with_relationships: GroupUserWithRelationships,
+                invite_id: i64,
+        ) -> Result<Option<bool>, Error> {
+                let group_user = GroupUser::from((group_id, group_user_with_relationships));
+
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                let saved = connection.transaction::<bool, diesel::result::Error, _>(|connection| {
+                        let inserted = diesel::insert_into(group_users::table)
+                                .values(&group_user)
+                                .on_conflict((group_users::group_id, group_users::user_id))
+                                .do_nothing()
+                                .execute(connection)?;
+
+                        if inserted == 0 {
+                                return Ok(false);
+                        }
+
+                        let updated =
+                                diesel::update(
+                                        group_invites::table
+                                                .filter(group_invites::id.eq(invite_id))
+                                                .filter(group_invites::revoked_at.is_null())
+                                                .filter(group_invites::expires_at.gt(Utc::now().naive_utc()))
+                                                .filter(group_invites::max_uses.is_null().or(group_invites::use_count
+                                                        .lt(group_invites::max_uses.assume_not_null()))),
+                                )
+                                .set((
+                                        group_invites::updated_at.eq(Utc::now().naive_utc()),
+                                        group_invites::use_count.eq(group_invites::use_count + 1),
+                                ))
+                                .execute(connection)?;
+
+                        if updated == 0 {
+                                return Err(diesel::result::Error::RollbackTransaction);
+                        }
+
+                        Ok(true)
+                });
+
+                match saved {
+                        Ok(saved) => Ok(Some(saved)),
+                        Err(diesel::result::Error::RollbackTransaction) => Ok(None),
+                        Err(_) => Err(Error::new(
+                                ErrorCode::InternalServerError,
+                                "Failed to query database".into(),
+                        )),
+                }
+        }
+
+        /// Inserts a new group and its members. Existing groups are changed through the targeted updates above.
         pub fn save(&self, group_with_relationships: GroupWithRelationships) -> Result<Group, Error> {
                 let group = Group {
                         id: group_with_relationships.id,
@@ -81,6 +427,9 @@ impl GroupRepository {
                         updated_at: group_with_relationships.updated_at,
                         name: group_with_relationships.name,
                         message_request_id: group_with_relationships.message_request_id,
+                        archived_at: group_with_relationships.archived_at,
+                        avatar_url: group_with_relationships.avatar_url,
+                        message_ttl_seconds: group_with_relationships.message_ttl_seconds,
                 };
 
                 let mut connection = self
@@ -90,33 +439,14 @@ impl GroupRepository {
 
                 let group = diesel::insert_into(groups::table)
                         .values(&group)
-                        .on_conflict(groups::id)
-                        .do_update()
-                        .set(&group)
                         .get_result::<Group>(&mut connection)
-                        .optional()
-                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?
-                        .ok_or(Error::new(
-                                ErrorCode::InternalServerError,
-                                "Failed to query database".into(),
-                        ))?;
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
 
                 for gu in group_with_relationships.users.into_iter() {
-                        let group_user = GroupUser {
-                                id: gu.id,
-                                created_at: gu.created_at,
-                                updated_at: gu.updated_at,
-                                group_id: group.id,
-                                user_id: gu.user.id,
-                                is_admin: gu.is_admin,
-                                nickname: gu.nickname,
-                        };
+                        let group_user = GroupUser::from((group.id, gu));
 
                         diesel::insert_into(group_users::table)
                                 .values(&group_user)
-                                .on_conflict(group_users::id)
-                                .do_update()
-                                .set(&group_user)
                                 .execute(&mut connection)
                                 .map_err(|_| {
                                         Error::new(ErrorCode::InternalServerError, "Failed to query database".into())
@@ -0,0 +1,59 @@
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
+use rspc::{Error, ErrorCode};
+
+use crate::{models::MessageReaction, schema::message_reactions};
+
+#[derive(Debug, Clone)]
+pub struct MessageReactionRepository {
+        pool: r2d2::Pool<ConnectionManager<PgConnection>>,
+}
+
+impl MessageReactionRepository {
+        pub fn new(pool: r2d2::Pool<ConnectionManager<PgConnection>>) -> Self {
+                Self { pool }
+        }
+
+        pub fn save(&self, message_reaction: MessageReaction) -> Result<MessageReaction, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::insert_into(message_reactions::table)
+                        .values(&message_reaction)
+                        .on_conflict((
+                                message_reactions::message_id,
+                                message_reactions::user_id,
+                                message_reactions::emoji,
+                        ))
+                        .do_nothing()
+                        .execute(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                Ok(message_reaction)
+        }
+
+        pub fn delete_by_message_id_and_user_id_and_emoji(
+                &self,
+                message_id: i64,
+                user_id: i64,
+                emoji: String,
+        ) -> Result<(), Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::delete(
+                        message_reactions::table.filter(message_reactions::message_id
+                                .eq(message_id)
+                                .and(message_reactions::user_id.eq(user_id))
+                                .and(message_reactions::emoji.eq(emoji))),
+                )
+                .execute(&mut connection)
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                Ok(())
+        }
+}
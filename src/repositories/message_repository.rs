@@ -1,18 +1,22 @@
 use std::collections::HashMap;
 
+use chrono::Utc;
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
+use diesel::sql_types::{Nullable, Timestamp};
 use rspc::{Error, ErrorCode};
 
 use crate::models::MessageWithGroup;
 use crate::{
         models::{
                 Group, GroupUser, GroupUserWithRelationships, GroupWithRelationships, Message, MessageContent,
-                MessageWithRelationships, MessageWithSource, User,
+                MessageKind, MessageReaction, MessageReply, MessageWithRelationships, MessageWithSource, User,
         },
-        schema::{groups, message_content, messages, users},
+        schema::{group_users, groups, message_content, message_content_revisions, message_reactions, messages, users},
 };
 
+define_sql_function!(fn coalesce(a: Nullable<Timestamp>, b: Timestamp) -> Timestamp);
+
 #[derive(Debug, Clone)]
 pub struct MessageRepository {
         pool: r2d2::Pool<ConnectionManager<PgConnection>>,
@@ -23,50 +27,89 @@ impl MessageRepository {
                 Self { pool }
         }
 
-        pub fn find_by_group_id(&self, group_id: i64) -> Result<Vec<MessageWithSource>, Error> {
+        pub fn find_by_group_id_and_user_id(
+                &self,
+                group_id: i64,
+                user_id: i64,
+                reply_to_id: Option<i64>,
+                before: Option<i64>,
+                after: Option<i64>,
+                limit: i64,
+        ) -> Result<Vec<MessageWithSource>, Error> {
                 let mut connection = self
                         .pool
                         .get()
                         .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
 
-                let messages = messages::table
+                let mut query = messages::table
+                        .inner_join(users::table.on(users::id.eq(messages::source_id)))
+                        .left_join(
+                                message_content::table.on(message_content::message_id
+                                        .eq(messages::id)
+                                        .and(message_content::user_id.eq(user_id))),
+                        )
+                        .inner_join(
+                                group_users::table.on(group_users::group_id
+                                        .eq(messages::group_id)
+                                        .and(group_users::user_id.eq(user_id))),
+                        )
                         .filter(messages::group_id.eq(group_id))
-                        .order_by(messages::id.desc())
-                        .load::<Message>(&mut connection)
-                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+                        // Tombstones have no content left, so only show them for messages sent after the caller joined
+                        .filter(message_content::user_id
+                                .is_not_null()
+                                .or(messages::deleted_at.is_not_null().and(messages::id.gt(group_users::id)))
+                                .or(messages::kind.eq(MessageKind::System)))
+                        .filter(messages::expires_at
+                                .is_null()
+                                .or(messages::expires_at.gt(Utc::now().naive_utc())))
+                        .select((
+                                messages::all_columns,
+                                users::all_columns,
+                                message_content::all_columns.nullable(),
+                        ))
+                        .into_boxed();
 
-                let users = users::table
-                        .filter(users::id.eq_any(messages.iter().map(|m| m.source_id)))
-                        .load::<User>(&mut connection)
-                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
-                let user_map: HashMap<i64, User> = users.into_iter().map(|u| (u.id, u)).collect();
+                if let Some(reply_to_id) = reply_to_id {
+                        query = query.filter(messages::reply_to_id.eq(reply_to_id));
+                }
 
-                let content = MessageContent::belonging_to(&messages)
-                        .load::<MessageContent>(&mut connection)
+                if let Some(before) = before {
+                        query = query.filter(messages::id.lt(before));
+                }
+
+                query = match after {
+                        Some(after) => query.filter(messages::id.gt(after)).order_by(messages::id.asc()),
+                        None => query.order_by(messages::id.desc()),
+                };
+
+                let messages = query
+                        .limit(limit)
+                        .load::<(Message, User, Option<MessageContent>)>(&mut connection)
                         .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
-                let content_map: HashMap<i64, Vec<MessageContent>> =
-                        content.into_iter().fold(HashMap::new(), |mut acc, content_item| {
-                                acc.entry(content_item.message_id)
-                                        .or_insert_with(Vec::new)
-                                        .push(content_item);
-                                acc
-                        });
 
-                let messages_with_relationships = messages
+                let mut reaction_map =
+                        Self::find_reactions(&mut connection, messages.iter().map(|(m, _, _)| m.id).collect())
+                                .map_err(|_| {
+                                        Error::new(ErrorCode::InternalServerError, "Failed to query database".into())
+                                })?;
+
+                let reply_map = Self::find_replies(
+                        &mut connection,
+                        messages.iter().filter_map(|(m, _, _)| m.reply_to_id).collect(),
+                        Some(user_id),
+                )
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                let messages_with_source = messages
                         .into_iter()
-                        .map(|m| {
-                                let user = user_map.get(&m.source_id).ok_or(Error::new(
-                                        ErrorCode::InternalServerError,
-                                        "Failed to query database".into(),
-                                ))?;
-                                let content = content_map.get(&m.id).cloned().unwrap_or_default();
-                                let content_map: HashMap<i64, String> =
-                                        content.into_iter().map(|mc| (mc.user_id, mc.content.clone())).collect();
-                                Ok(MessageWithSource::from((m, user.clone(), content_map)))
+                        .map(|(message, source, content)| {
+                                let reactions = reaction_map.remove(&message.id).unwrap_or_default();
+                                let reply_to = message.reply_to_id.and_then(|id| reply_map.get(&id).cloned());
+                                MessageWithSource::from((message, source, content, reactions, reply_to))
                         })
-                        .collect::<Result<Vec<MessageWithSource>, Error>>()?;
+                        .collect();
 
-                Ok(messages_with_relationships)
+                Ok(messages_with_source)
         }
 
         pub fn find_by_user_id(&self, user_id: i64) -> Result<Vec<MessageWithGroup>, Error> {
@@ -79,6 +122,9 @@ impl MessageRepository {
                         .inner_join(messages::table.on(messages::id.eq(message_content::message_id)))
                         .inner_join(users::table.on(users::id.eq(messages::source_id)))
                         .filter(message_content::user_id.eq(user_id))
+                        .filter(messages::expires_at
+                                .is_null()
+                                .or(messages::expires_at.gt(Utc::now().naive_utc())))
                         .distinct_on(messages::group_id)
                         .order_by((messages::group_id, message_content::message_id.desc()))
                         .select((messages::all_columns, users::all_columns, message_content::all_columns))
@@ -113,6 +159,19 @@ impl MessageRepository {
                                 acc
                         });
 
+                let mut reaction_map =
+                        Self::find_reactions(&mut connection, messages.iter().map(|(m, _, _)| m.id).collect())
+                                .map_err(|_| {
+                                        Error::new(ErrorCode::InternalServerError, "Failed to query database".into())
+                                })?;
+
+                let reply_map = Self::find_replies(
+                        &mut connection,
+                        messages.iter().filter_map(|(m, _, _)| m.reply_to_id).collect(),
+                        Some(user_id),
+                )
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
                 let messages_with_group = messages
                         .into_iter()
                         .filter_map(|(message, source, content)| {
@@ -123,7 +182,16 @@ impl MessageRepository {
                                                 .unwrap_or_default();
                                         let group_with_relationships =
                                                 GroupWithRelationships::from((group.clone(), group_users));
-                                        MessageWithGroup::from((message, group_with_relationships, source, content))
+                                        let reactions = reaction_map.remove(&message.id).unwrap_or_default();
+                                        let reply_to = message.reply_to_id.and_then(|id| reply_map.get(&id).cloned());
+                                        MessageWithGroup::from((
+                                                message,
+                                                group_with_relationships,
+                                                source,
+                                                content,
+                                                reactions,
+                                                reply_to,
+                                        ))
                                 })
                         })
                         .collect();
@@ -131,41 +199,370 @@ impl MessageRepository {
                 Ok(messages_with_group)
         }
 
+        pub fn count_unread_by_user_id(&self, user_id: i64, group_ids: Vec<i64>) -> Result<HashMap<i64, i64>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                let unread_counts = message_content::table
+                        .inner_join(messages::table.on(messages::id.eq(message_content::message_id)))
+                        .inner_join(
+                                group_users::table.on(group_users::group_id
+                                        .eq(messages::group_id)
+                                        .and(group_users::user_id.eq(message_content::user_id))),
+                        )
+                        .filter(message_content::user_id.eq(user_id))
+                        .filter(messages::group_id.eq_any(group_ids))
+                        .filter(messages::source_id.ne(user_id))
+                        .filter(messages::deleted_at.is_null())
+                        .filter(messages::expires_at
+                                .is_null()
+                                .or(messages::expires_at.gt(Utc::now().naive_utc())))
+                        .filter(group_users::last_read_message_id
+                                .is_null()
+                                .or(messages::id.gt(group_users::last_read_message_id.assume_not_null())))
+                        .group_by(messages::group_id)
+                        .select((messages::group_id, diesel::dsl::count_star()))
+                        .load::<(i64, i64)>(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                Ok(unread_counts.into_iter().collect())
+        }
+
+        pub fn find_by_id_and_group(
+                &self,
+                message_id: i64,
+                group: GroupWithRelationships,
+        ) -> Result<Option<MessageWithRelationships>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                let message = messages::table
+                        .inner_join(users::table.on(users::id.eq(messages::source_id)))
+                        .filter(messages::id.eq(message_id).and(messages::group_id.eq(group.id)))
+                        .select((messages::all_columns, users::all_columns))
+                        .first::<(Message, User)>(&mut connection)
+                        .optional()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                message.map_or(Ok(None), |(message, source)| {
+                        let content: HashMap<i64, String> = MessageContent::belonging_to(&message)
+                                .load::<MessageContent>(&mut connection)
+                                .map_err(|_| {
+                                        Error::new(ErrorCode::InternalServerError, "Failed to query database".into())
+                                })?
+                                .into_iter()
+                                .map(|mc| (mc.user_id, mc.content))
+                                .collect();
+
+                        let reactions = Self::find_reactions(&mut connection, vec![message.id])
+                                .map_err(|_| {
+                                        Error::new(ErrorCode::InternalServerError, "Failed to query database".into())
+                                })?
+                                .remove(&message.id)
+                                .unwrap_or_default();
+
+                        let reply_to = match message.reply_to_id {
+                                Some(reply_to_id) => Self::find_replies(&mut connection, vec![reply_to_id], None)
+                                        .map_err(|_| {
+                                                Error::new(
+                                                        ErrorCode::InternalServerError,
+                                                        "Failed to query database".into(),
+                                                )
+                                        })?
+                                        .remove(&reply_to_id),
+                                None => None,
+                        };
+
+                        Ok(Some(MessageWithRelationships::from((
+                                message, group, source, content, reactions, reply_to,
+                        ))))
+                })
+        }
+
+        pub fn find_by_group_and_source_id_and_idempotency_key(
+                &self,
+                group: GroupWithRelationships,
+                source_id: i64,
+                idempotency_key: &str,
+        ) -> Result<Option<MessageWithRelationships>, Error> {
+                let message_id = {
+                        let mut connection = self.pool.get().map_err(|_| {
+                                Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into())
+                        })?;
+
+                        messages::table
+                                .filter(messages::group_id
+                                        .eq(group.id)
+                                        .and(messages::source_id.eq(source_id))
+                                        .and(messages::idempotency_key.eq(idempotency_key)))
+                                .select(messages::id)
+                                .first::<i64>(&mut connection)
+                                .optional()
+                                .map_err(|_| {
+                                        Error::new(ErrorCode::InternalServerError, "Failed to query database".into())
+                                })?
+                };
+
+                message_id.map_or(Ok(None), |message_id| self.find_by_id_and_group(message_id, group))
+        }
+
+        pub fn exists_by_id_and_group_id(&self, message_id: i64, group_id: i64) -> Result<bool, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                diesel::select(diesel::dsl::exists(
+                        messages::table.filter(messages::id.eq(message_id).and(messages::group_id.eq(group_id))),
+                ))
+                .get_result(&mut connection)
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
         pub fn save(
                 &self,
                 messages_with_relatioships: MessageWithRelationships,
         ) -> Result<MessageWithRelationships, Error> {
-                let message = Message {
-                        id: messages_with_relatioships.id,
-                        created_at: messages_with_relatioships.created_at,
-                        updated_at: messages_with_relatioships.updated_at,
-                        group_id: messages_with_relatioships.group.id,
-                        source_id: messages_with_relatioships.source.id,
-                        idempotency_key: messages_with_relatioships.idempotency_key,
-                };
+                let message = Message::from(&messages_with_relatioships);
 
                 let mut connection = self
                         .pool
                         .get()
                         .map_err(|_| Error::new(ErrorCode::InternalServerError, "failed to pool connection".into()))?;
 
+                let message = Self::upsert(&mut connection, &message, &messages_with_relatioships.content)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                let message_with_relationships = MessageWithRelationships::from((
+                        message,
+                        messages_with_relatioships.group,
+                        messages_with_relatioships.source,
+                        messages_with_relatioships.content,
+                        messages_with_relatioships.reactions,
+                        messages_with_relatioships.reply_to,
+                ));
+
+                Ok(message_with_relationships)
+        }
+
+        pub fn save_revision(
+                &self,
+                messages_with_relatioships: MessageWithRelationships,
+        ) -> Result<MessageWithRelationships, Error> {
+                let message = Message::from(&messages_with_relatioships);
+
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                let message = connection
+                        .transaction::<Message, diesel::result::Error, _>(|connection| {
+                                diesel::insert_into(message_content_revisions::table)
+                                        // Date each revision from when the superseded content was written
+                                        .values(message_content::table
+                                                .inner_join(
+                                                        messages::table
+                                                                .on(messages::id.eq(message_content::message_id)),
+                                                )
+                                                .filter(message_content::message_id.eq(message.id))
+                                                .select((
+                                                        message_content::message_id,
+                                                        message_content::user_id,
+                                                        coalesce(messages::edited_at, messages::created_at),
+                                                        message_content::content,
+                                                )))
+                                        .into_columns((
+                                                message_content_revisions::message_id,
+                                                message_content_revisions::user_id,
+                                                message_content_revisions::created_at,
+                                                message_content_revisions::content,
+                                        ))
+                                        .execute(connection)?;
+
+                                Self::upsert(connection, &message, &messages_with_relatioships.content)
+                        })
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                let message_with_relationships = MessageWithRelationships::from((
+                        message,
+                        messages_with_relatioships.group,
+                        messages_with_relatioships.source,
+                        messages_with_relatioships.content,
+                        messages_with_relatioships.reactions,
+                        messages_with_relatioships.reply_to,
+                ));
+
+                Ok(message_with_relationships)
+        }
+
+        pub fn save_tombstone(
+                &self,
+                messages_with_relatioships: MessageWithRelationships,
+        ) -> Result<MessageWithRelationships, Error> {
+                let message = Message::from(&messages_with_relatioships);
+
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                let message = connection
+                        .transaction::<Message, diesel::result::Error, _>(|connection| {
+                                diesel::delete(
+                                        message_content_revisions::table
+                                                .filter(message_content_revisions::message_id.eq(message.id)),
+                                )
+                                .execute(connection)?;
+
+                                diesel::delete(
+                                        message_reactions::table.filter(message_reactions::message_id.eq(message.id)),
+                                )
+                                .execute(connection)?;
+
+                                diesel::delete(
+                                        message_content::table.filter(message_content::message_id.eq(message.id)),
+                                )
+                                .execute(connection)?;
+
+                                Self::upsert(connection, &message, &HashMap::new())
+                        })
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?;
+
+                let message_with_relationships = MessageWithRelationships::from((
+                        message,
+                        messages_with_relatioships.group,
+                        messages_with_relatioships.source,
+                        HashMap::new(),
+                        Vec::new(),
+                        messages_with_relatioships.reply_to,
+                ));
+
+                Ok(message_with_relationships)
+        }
+
+        /// Hard-deletes every message that has expired, returning the `(id, group_id)` of each one.
+        pub fn delete_expired(&self) -> Result<Vec<(i64, i64)>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                connection
+                        .transaction::<Vec<(i64, i64)>, diesel::result::Error, _>(|connection| {
+                                let expired_messages = messages::table
+                                        .filter(messages::expires_at.le(Utc::now().naive_utc()))
+                                        .select((messages::id, messages::group_id))
+                                        .for_update()
+                                        .skip_locked()
+                                        .load::<(i64, i64)>(connection)?;
+
+                                if expired_messages.is_empty() {
+                                        return Ok(expired_messages);
+                                }
+
+                                let message_ids: Vec<i64> = expired_messages.iter().map(|(id, _)| *id).collect();
+
+                                diesel::update(messages::table.filter(messages::reply_to_id.eq_any(&message_ids)))
+                                        .set(messages::reply_to_id.eq(None::<i64>))
+                                        .execute(connection)?;
+
+                                diesel::delete(
+                                        message_content_revisions::table
+                                                .filter(message_content_revisions::message_id.eq_any(&message_ids)),
+                                )
+                                .execute(connection)?;
+
+                                diesel::delete(
+                                        message_reactions::table
+                                                .filter(message_reactions::message_id.eq_any(&message_ids)),
+                                )
+                                .execute(connection)?;
+
+                                diesel::delete(
+                                        message_content::table.filter(message_content::message_id.eq_any(&message_ids)),
+                                )
+                                .execute(connection)?;
+
+                                diesel::delete(messages::table.filter(messages::id.eq_any(&message_ids)))
+                                        .execute(connection)?;
+
+                                Ok(expired_messages)
+                        })
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
+        fn find_reactions(
+                connection: &mut PgConnection,
+                message_ids: Vec<i64>,
+        ) -> QueryResult<HashMap<i64, Vec<MessageReaction>>> {
+                let reactions = message_reactions::table
+                        .filter(message_reactions::message_id.eq_any(message_ids))
+                        .order_by(message_reactions::created_at.asc())
+                        .load::<MessageReaction>(connection)?;
+
+                Ok(reactions.into_iter().fold(HashMap::new(), |mut acc, reaction| {
+                        acc.entry(reaction.message_id).or_insert_with(Vec::new).push(reaction);
+                        acc
+                }))
+        }
+
+        fn find_replies(
+                connection: &mut PgConnection,
+                message_ids: Vec<i64>,
+                user_id: Option<i64>,
+        ) -> QueryResult<HashMap<i64, MessageReply>> {
+                let messages = messages::table
+                        .inner_join(users::table.on(users::id.eq(messages::source_id)))
+                        .filter(messages::id.eq_any(&message_ids))
+                        .select((messages::all_columns, users::all_columns))
+                        .load::<(Message, User)>(connection)?;
+
+                let mut content_query = message_content::table
+                        .filter(message_content::message_id.eq_any(&message_ids))
+                        .into_boxed();
+
+                if let Some(user_id) = user_id {
+                        content_query = content_query.filter(message_content::user_id.eq(user_id));
+                }
+
+                let mut content_map: HashMap<i64, HashMap<i64, String>> = content_query
+                        .load::<MessageContent>(connection)?
+                        .into_iter()
+                        .fold(HashMap::new(), |mut acc, mc| {
+                                acc.entry(mc.message_id).or_default().insert(mc.user_id, mc.content);
+                                acc
+                        });
+
+                Ok(messages
+                        .into_iter()
+                        .map(|(message, source)| {
+                                let content = content_map.remove(&message.id).unwrap_or_default();
+                                (message.id, MessageReply::from((message, source, content)))
+                        })
+                        .collect())
+        }
+
+        fn upsert(
+                connection: &mut PgConnection,
+                message: &Message,
+                content: &HashMap<i64, String>,
+        ) -> QueryResult<Message> {
                 let message = diesel::insert_into(messages::table)
-                        .values(&message)
+                        .values(message)
                         .on_conflict(messages::id)
                         .do_update()
-                        .set(&message)
-                        .get_result::<Message>(&mut connection)
-                        .optional()
-                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))?
-                        .ok_or(Error::new(
-                                ErrorCode::InternalServerError,
-                                "failed to query database".into(),
-                        ))?;
+                        .set(message)
+                        .get_result::<Message>(connection)?;
 
-                for (user_id, content) in &messages_with_relatioships.content {
+                for (user_id, content) in content {
                         let message_content = MessageContent {
                                 message_id: message.id,
-                                user_id: user_id.clone(),
+                                user_id: *user_id,
                                 content: content.clone(),
                         };
 
@@ -174,19 +571,9 @@ impl MessageRepository {
                                 .on_conflict((message_content::message_id, message_content::user_id))
                                 .do_update()
                                 .set(&message_content)
-                                .execute(&mut connection)
-                                .map_err(|_| {
-                                        Error::new(ErrorCode::InternalServerError, "Failed to query database".into())
-                                })?;
+                                .execute(connection)?;
                 }
 
-                let message_with_relationships = MessageWithRelationships::from((
-                        message,
-                        messages_with_relatioships.group,
-                        messages_with_relatioships.source,
-                        messages_with_relatioships.content,
-                ));
-
-                Ok(message_with_relationships)
+                Ok(message)
         }
 }
@@ -1,9 +1,16 @@
+use chrono::Utc;
 use diesel::alias;
+use diesel::dsl::exists;
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 use rspc::{Error, ErrorCode};
 
-use crate::models::{MessageRequest, MessageRequestWithRelationships, User};
+use crate::models::{
+        Group, GroupUser, GroupWithRelationships, MessageRequest, MessageRequestStatus,
+        MessageRequestWithRelationships, User,
+};
+use crate::schema::group_users;
+use crate::schema::groups;
 use crate::schema::message_requests;
 use crate::schema::users;
 
@@ -57,22 +64,301 @@ impl MessageRequestRepository {
                 })
         }
 
-        pub fn exists_by_source_id_and_destination_id(
+        pub fn find_by_id_and_source_id(
                 &self,
+                message_request_id: i64,
                 source_id: i64,
+        ) -> Result<Option<MessageRequestWithRelationships>, Error> {
+                let mut connection = self.pool.get().map_err(|_| {
+                        Error::new(ErrorCode::InternalServerError, "Failed to pool connection".to_string())
+                })?;
+
+                let destination_users = alias!(users as destination_users);
+
+                let response = message_requests::table
+                        .inner_join(users::table.on(users::id.eq(message_requests::source_id)))
+                        .inner_join(
+                                destination_users
+                                        .on(destination_users.field(users::id).eq(message_requests::destination_id)),
+                        )
+                        .filter(message_requests::id
+                                .eq(message_request_id)
+                                .and(message_requests::source_id.eq(source_id)))
+                        .select((
+                                message_requests::all_columns,
+                                users::all_columns,
+                                destination_users.fields(users::all_columns),
+                        ))
+                        .first::<(MessageRequest, User, User)>(&mut connection)
+                        .optional()
+                        .map_err(|_| {
+                                Error::new(ErrorCode::InternalServerError, "Failed to query database".to_string())
+                        })?;
+
+                Ok(response.map(MessageRequestWithRelationships::from))
+        }
+
+        pub fn find_by_destination_id(
+                &self,
                 destination_id: i64,
-        ) -> Result<bool, Error> {
+                status: Option<MessageRequestStatus>,
+                before: Option<i64>,
+                limit: i64,
+        ) -> Result<Vec<MessageRequestWithRelationships>, Error> {
+                self.find_page(destination_id, true, status, before, limit)
+        }
+
+        pub fn find_by_source_id(
+                &self,
+                source_id: i64,
+                status: Option<MessageRequestStatus>,
+                before: Option<i64>,
+                limit: i64,
+        ) -> Result<Vec<MessageRequestWithRelationships>, Error> {
+                self.find_page(source_id, false, status, before, limit)
+        }
+
+        fn find_page(
+                &self,
+                user_id: i64,
+                incoming: bool,
+                status: Option<MessageRequestStatus>,
+                before: Option<i64>,
+                limit: i64,
+        ) -> Result<Vec<MessageRequestWithRelationships>, Error> {
+                let mut connection = self.pool.get().map_err(|_| {
+                        Error::new(ErrorCode::InternalServerError, "Failed to pool connection".to_string())
+                })?;
+
+                let destination_users = alias!(users as destination_users);
+
+                let mut query = message_requests::table
+                        .inner_join(users::table.on(users::id.eq(message_requests::source_id)))
+                        .inner_join(
+                                destination_users
+                                        .on(destination_users.field(users::id).eq(message_requests::destination_id)),
+                        )
+                        .select((
+                                message_requests::all_columns,
+                                users::all_columns,
+                                destination_users.fields(users::all_columns),
+                        ))
+                        .into_boxed();
+
+                query = if incoming {
+                        query.filter(message_requests::destination_id.eq(user_id))
+                } else {
+                        query.filter(message_requests::source_id.eq(user_id))
+                };
+
+                if let Some(status) = status {
+                        query = query.filter(message_requests::status.eq(status));
+                }
+
+                if let Some(before) = before {
+                        query = query.filter(message_requests::id.lt(before));
+                }
+
+                let response = query
+                        .order(message_requests::id.desc())
+                        .limit(limit)
+                        .load::<(MessageRequest, User, User)>(&mut connection)
+                        .map_err(|_| {
+                                Error::new(ErrorCode::InternalServerError, "Failed to query database".to_string())
+                        })?;
+
+                Ok(response
+                        .into_iter()
+                        .map(MessageRequestWithRelationships::from)
+                        .collect())
+        }
+
+        pub fn find_open_by_user_ids(
+                &self,
+                user_id: i64,
+                other_user_id: i64,
+        ) -> Result<Option<MessageRequestWithRelationships>, Error> {
                 let mut connection = self.pool.get().map_err(|_| {
                         Error::new(ErrorCode::InternalServerError, "Failed to pool connection".to_string())
                 })?;
 
-                diesel::select(diesel::dsl::exists(
-                        message_requests::table.filter(message_requests::source_id
+                let destination_users = alias!(users as destination_users);
+
+                let response = message_requests::table
+                        .inner_join(users::table.on(users::id.eq(message_requests::source_id)))
+                        .inner_join(
+                                destination_users
+                                        .on(destination_users.field(users::id).eq(message_requests::destination_id)),
+                        )
+                        .filter(message_requests::source_id
+                                .eq(user_id)
+                                .and(message_requests::destination_id.eq(other_user_id))
+                                .or(message_requests::source_id
+                                        .eq(other_user_id)
+                                        .and(message_requests::destination_id.eq(user_id))))
+                        // An approved request only stays open while its conversation is live for both users
+                        .filter(message_requests::status
+                                .eq(MessageRequestStatus::Pending)
+                                .or(message_requests::status
+                                        .eq(MessageRequestStatus::Approved)
+                                        .and(exists(groups::table
+                                                .filter(groups::id.nullable().eq(message_requests::group_id))
+                                                .filter(groups::archived_at.is_null())))
+                                        .and(exists(group_users::table
+                                                .filter(group_users::group_id.nullable().eq(message_requests::group_id))
+                                                .filter(group_users::user_id.eq(user_id))))
+                                        .and(exists(group_users::table
+                                                .filter(group_users::group_id.nullable().eq(message_requests::group_id))
+                                                .filter(group_users::user_id.eq(other_user_id))))))
+                        .select((
+                                message_requests::all_columns,
+                                users::all_columns,
+                                destination_users.fields(users::all_columns),
+                        ))
+                        .order(message_requests::id.desc())
+                        .first::<(MessageRequest, User, User)>(&mut connection)
+                        .optional()
+                        .map_err(|_| {
+                                Error::new(ErrorCode::InternalServerError, "Failed to query database".to_string())
+                        })?;
+
+                Ok(response.map(MessageRequestWithRelationships::from))
+        }
+
+        pub fn find_last_declined_at_by_source_id_and_destination_id(
+                &self,
+                source_id: i64,
+                destination_id: i64,
+        ) -> Result<Option<chrono::NaiveDateTime>, Error> {
+                let mut connection = self.pool.get().map_err(|_| {
+                        Error::new(ErrorCode::InternalServerError, "Failed to pool connection".to_string())
+                })?;
+
+                message_requests::table
+                        .filter(message_requests::source_id
                                 .eq(source_id)
-                                .and(message_requests::destination_id.eq(destination_id))),
+                                .and(message_requests::destination_id.eq(destination_id)))
+                        .filter(message_requests::status.eq(MessageRequestStatus::Declined))
+                        .select(diesel::dsl::max(message_requests::updated_at))
+                        .get_result(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".to_string()))
+        }
+
+        pub fn find_approved_user_ids_by_user_id(&self, user_id: i64, user_ids: Vec<i64>) -> Result<Vec<i64>, Error> {
+                let mut connection = self.pool.get().map_err(|_| {
+                        Error::new(ErrorCode::InternalServerError, "Failed to pool connection".to_string())
+                })?;
+
+                let message_requests = message_requests::table
+                        .filter(message_requests::approved_at.is_not_null())
+                        .filter(message_requests::source_id
+                                .eq(user_id)
+                                .and(message_requests::destination_id.eq_any(&user_ids))
+                                .or(message_requests::destination_id
+                                        .eq(user_id)
+                                        .and(message_requests::source_id.eq_any(&user_ids))))
+                        .select((message_requests::source_id, message_requests::destination_id))
+                        .load::<(i64, i64)>(&mut connection)
+                        .map_err(|_| {
+                                Error::new(ErrorCode::InternalServerError, "Failed to query database".to_string())
+                        })?;
+
+                let approved_user_ids = message_requests
+                        .into_iter()
+                        .map(|(source_id, destination_id)| {
+                                if source_id == user_id {
+                                        destination_id
+                                } else {
+                                        source_id
+                                }
+                        })
+                        .collect();
+
+                Ok(approved_user_ids)
+        }
+
+        /// Moves a pending request to the given status. Returns false when it is no longer pending.
+        pub fn update_status_if_pending(
+                &self,
+                message_request_id: i64,
+                status: MessageRequestStatus,
+        ) -> Result<bool, Error> {
+                let mut connection = self.pool.get().map_err(|_| {
+                        Error::new(ErrorCode::InternalServerError, "Failed to pool connection".to_string())
+                })?;
+
+                let updated = diesel::update(
+                        message_requests::table
+                                .filter(message_requests::id.eq(message_request_id))
+                                .filter(message_requests::status.eq(MessageRequestStatus::Pending)),
+                )
+                .set((
+                        message_requests::updated_at.eq(Utc::now().naive_utc()),
+                        message_requests::status.eq(status),
                 ))
-                .get_result(&mut connection)
-                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".to_string()))
+                .execute(&mut connection)
+                .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".to_string()))?;
+
+                Ok(updated > 0)
+        }
+
+        /// Approves a pending request and creates its group in one transaction. Returns false without
+        /// changing anything when the request is no longer pending, e.g. because it was already approved.
+        pub fn approve(
+                &self,
+                message_request_id: i64,
+                group_with_relationships: GroupWithRelationships,
+        ) -> Result<bool, Error> {
+                let group = Group {
+                        id: group_with_relationships.id,
+                        created_at: group_with_relationships.created_at,
+                        updated_at: group_with_relationships.updated_at,
+                        name: group_with_relationships.name,
+                        message_request_id: group_with_relationships.message_request_id,
+                        archived_at: group_with_relationships.archived_at,
+                        avatar_url: group_with_relationships.avatar_url,
+                        message_ttl_seconds: group_with_relationships.message_ttl_seconds,
+                };
+
+                let group_users = group_with_relationships
+                        .users
+                        .into_iter()
+                        .map(|gu| GroupUser::from((group.id, gu)))
+                        .collect::<Vec<GroupUser>>();
+
+                let mut connection = self.pool.get().map_err(|_| {
+                        Error::new(ErrorCode::InternalServerError, "Failed to pool connection".to_string())
+                })?;
+
+                connection
+                        .transaction::<bool, diesel::result::Error, _>(|connection| {
+                                let status = message_requests::table
+                                        .find(message_request_id)
+                                        .select(message_requests::status)
+                                        .for_update()
+                                        .first::<MessageRequestStatus>(connection)?;
+
+                                if status != MessageRequestStatus::Pending {
+                                        return Ok(false);
+                                }
+
+                                diesel::insert_into(groups::table).values(&group).execute(connection)?;
+                                diesel::insert_into(group_users::table)
+                                        .values(&group_users)
+                                        .execute(connection)?;
+
+                                diesel::update(message_requests::table.find(message_request_id))
+                                        .set((
+                                                message_requests::updated_at.eq(group.created_at),
+                                                message_requests::approved_at.eq(group.created_at),
+                                                message_requests::status.eq(MessageRequestStatus::Approved),
+                                                message_requests::group_id.eq(group.id),
+                                        ))
+                                        .execute(connection)?;
+
+                                Ok(true)
+                        })
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".to_string()))
         }
 
         pub fn save(
@@ -86,6 +372,8 @@ impl MessageRequestRepository {
                         source_id: message_request_with_relationships.source.id,
                         destination_id: message_request_with_relationships.destination.id,
                         approved_at: message_request_with_relationships.approved_at,
+                        status: message_request_with_relationships.status,
+                        group_id: message_request_with_relationships.group_id,
                 };
 
                 let mut connection = self.pool.get().map_err(|_| {
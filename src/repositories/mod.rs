@@ -1,4 +1,6 @@
+pub mod group_invite_repository;
 pub mod group_repository;
+pub mod message_reaction_repository;
 pub mod message_repository;
 pub mod message_request_repository;
 pub mod user_push_subscription_repository;
@@ -25,6 +25,18 @@ impl UserRepository {
                         .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
         }
 
+        pub fn find_by_ids(&self, user_ids: Vec<i64>) -> Result<Vec<User>, Error> {
+                let mut connection = self
+                        .pool
+                        .get()
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to pool connection".into()))?;
+
+                users::table
+                        .filter(users::id.eq_any(user_ids))
+                        .load(&mut connection)
+                        .map_err(|_| Error::new(ErrorCode::InternalServerError, "Failed to query database".into()))
+        }
+
         pub fn find_by_sub(&self, sub: String) -> Result<Option<User>, Error> {
                 let mut connection = self
                         .pool
@@ -1,5 +1,21 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    group_invites (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        group_id -> Int8,
+        source_id -> Int8,
+        #[max_length = 64]
+        token -> Varchar,
+        expires_at -> Timestamp,
+        max_uses -> Nullable<Int4>,
+        use_count -> Int4,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     group_users (id) {
         id -> Int8,
@@ -7,9 +23,16 @@ diesel::table! {
         updated_at -> Timestamp,
         group_id -> Int8,
         user_id -> Int8,
-        is_admin -> Bool,
         #[max_length = 255]
         nickname -> Nullable<Varchar>,
+        last_delivered_message_id -> Nullable<Int8>,
+        last_read_message_id -> Nullable<Int8>,
+        marked_unread -> Bool,
+        #[max_length = 32]
+        role -> Varchar,
+        muted_until -> Nullable<Timestamp>,
+        archived -> Bool,
+        pinned -> Bool,
     }
 }
 
@@ -21,6 +44,10 @@ diesel::table! {
         #[max_length = 255]
         name -> Nullable<Varchar>,
         message_request_id -> Nullable<Int8>,
+        archived_at -> Nullable<Timestamp>,
+        #[max_length = 2048]
+        avatar_url -> Nullable<Varchar>,
+        message_ttl_seconds -> Nullable<Int4>,
     }
 }
 
@@ -32,6 +59,25 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    message_content_revisions (message_id, user_id, created_at) {
+        message_id -> Int8,
+        user_id -> Int8,
+        created_at -> Timestamp,
+        content -> Text,
+    }
+}
+
+diesel::table! {
+    message_reactions (message_id, user_id, emoji) {
+        message_id -> Int8,
+        user_id -> Int8,
+        #[max_length = 32]
+        emoji -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     message_requests (id) {
         id -> Int8,
@@ -40,6 +86,9 @@ diesel::table! {
         source_id -> Int8,
         destination_id -> Int8,
         approved_at -> Nullable<Timestamp>,
+        #[max_length = 32]
+        status -> Varchar,
+        group_id -> Nullable<Int8>,
     }
 }
 
@@ -52,6 +101,13 @@ diesel::table! {
         source_id -> Int8,
         #[max_length = 255]
         idempotency_key -> Nullable<Varchar>,
+        edited_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+        reply_to_id -> Nullable<Int8>,
+        #[max_length = 32]
+        kind -> Varchar,
+        metadata -> Nullable<Jsonb>,
+        expires_at -> Nullable<Timestamp>,
     }
 }
 
@@ -87,17 +143,26 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(group_invites -> groups (group_id));
+diesel::joinable!(group_invites -> users (source_id));
 diesel::joinable!(group_users -> groups (group_id));
 diesel::joinable!(group_users -> users (user_id));
 diesel::joinable!(groups -> message_requests (message_request_id));
+diesel::joinable!(message_content_revisions -> messages (message_id));
+diesel::joinable!(message_content_revisions -> users (user_id));
+diesel::joinable!(message_reactions -> messages (message_id));
+diesel::joinable!(message_reactions -> users (user_id));
 diesel::joinable!(messages -> groups (group_id));
 diesel::joinable!(messages -> users (source_id));
 diesel::joinable!(user_push_subscriptions -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    group_invites,
     group_users,
     groups,
     message_content,
+    message_content_revisions,
+    message_reactions,
     message_requests,
     messages,
     user_push_subscriptions,
@@ -3,6 +3,7 @@ use std::env;
 use derive_new::new;
 use google_cloud_storage::{
         client::Client,
+        http::{objects::get::GetObjectRequest, Error as StorageError},
         sign::{SignedURLMethod, SignedURLOptions},
 };
 use rspc::{Error, ErrorCode};
@@ -17,6 +18,49 @@ impl GoogleCloudStorageService {
                 let bucket = env::var("GCP_USER_PROFILE_PICTURE_BUCKET")
                         .expect("GCP_USER_PROFILE_PICTURE_BUCKET must be set");
 
+                self.sign_upload_url(bucket, key, content_type).await
+        }
+
+        pub async fn get_group_avatar_presigned_upload_url(
+                &self,
+                key: String,
+                content_type: String,
+        ) -> Result<String, Error> {
+                let bucket = env::var("GCP_GROUP_AVATAR_BUCKET").expect("GCP_GROUP_AVATAR_BUCKET must be set");
+
+                self.sign_upload_url(bucket, key, content_type).await
+        }
+
+        /// Returns the generation of the uploaded group avatar, or None if nothing has been uploaded yet.
+        pub async fn get_group_avatar_generation(&self, key: String) -> Result<Option<i64>, Error> {
+                let bucket = env::var("GCP_GROUP_AVATAR_BUCKET").expect("GCP_GROUP_AVATAR_BUCKET must be set");
+
+                let object = self
+                        .client
+                        .get_object(&GetObjectRequest {
+                                bucket,
+                                object: key,
+                                ..Default::default()
+                        })
+                        .await;
+
+                match object {
+                        Ok(object) => Ok(Some(object.generation)),
+                        Err(StorageError::Response(response)) if response.code == 404 => Ok(None),
+                        Err(_) => Err(Error::new(
+                                ErrorCode::InternalServerError,
+                                "Failed to get object".into(),
+                        )),
+                }
+        }
+
+        pub fn get_group_avatar_public_url(&self, key: String) -> String {
+                let bucket = env::var("GCP_GROUP_AVATAR_BUCKET").expect("GCP_GROUP_AVATAR_BUCKET must be set");
+
+                format!("https://storage.googleapis.com/{}/{}", bucket, key)
+        }
+
+        async fn sign_upload_url(&self, bucket: String, key: String, content_type: String) -> Result<String, Error> {
                 let presigned_url = self
                         .client
                         .signed_url(